@@ -0,0 +1,120 @@
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::config::{ConfigArgs, Quota};
+use crate::quota;
+use crate::NoteStore;
+
+// --- Constants ---
+const DEFAULT_LOG_PAGE: usize = 50;
+const MAX_LOG_PAGE: usize = 200;
+
+// --- Data Structures ---
+/// A principal barred from writing until an admin lifts the suspension.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Suspension {
+    pub reason: String,
+    pub suspended_by: Principal,
+    pub suspended_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
+pub enum AdminAction {
+    AddAdmin { principal: Principal },
+    RemoveAdmin { principal: Principal },
+    ForceDeleteNote { id: u64, owner: Principal, reason: String },
+    SuspendPrincipal {
+        principal: Principal,
+        reason: String,
+        /// The suspension this one replaced, if the principal was already suspended.
+        replaced: Option<Suspension>,
+    },
+    UnsuspendPrincipal { principal: Principal },
+    UpdateConfig { args: ConfigArgs },
+    SetQuotaOverride { principal: Principal, quota: Option<Quota> },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
+pub struct AdminLogEntry {
+    pub seq: u64,
+    pub admin: Principal,
+    pub at: u64,
+    pub action: AdminAction,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AdminLogPage {
+    pub entries: Vec<AdminLogEntry>,
+    /// Pass as `cursor` to fetch older entries; `None` once the start is reached.
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct GlobalStats {
+    pub total_notes: u64,
+    pub total_bytes: u64,
+    pub distinct_owners: u64,
+    pub anonymous_notes: u64,
+    pub ephemeral_notes: u64,
+    pub encrypted_notes: u64,
+    pub open_import_jobs: u64,
+    pub admins: u64,
+    pub suspended_principals: u64,
+    /// Distinct note contents in the blob store, and the bytes they take.
+    pub unique_contents: u64,
+    pub stored_content_bytes: u64,
+}
+
+/// Note totals across all owners; the caller fills in the non-note counters.
+pub fn note_stats(notes: &NoteStore) -> GlobalStats {
+    let mut owners: Vec<Principal> = notes.values().map(|note| note.owner).collect();
+    owners.sort();
+    owners.dedup();
+    GlobalStats {
+        total_notes: notes.len() as u64,
+        total_bytes: notes.values().map(quota::note_bytes).sum(),
+        distinct_owners: owners.len() as u64,
+        anonymous_notes: notes.values().filter(|note| note.owner == Principal::anonymous()).count() as u64,
+        ephemeral_notes: notes.values().filter(|note| note.expires_at.is_some()).count() as u64,
+        encrypted_notes: notes.values().filter(|note| note.encrypted.is_some()).count() as u64,
+        ..GlobalStats::default()
+    }
+}
+
+/// Newest-first page of the log. `cursor` is the `seq` to start below.
+pub fn log_page(log: &[AdminLogEntry], cursor: Option<u64>, limit: Option<u32>) -> AdminLogPage {
+    let limit = limit.map_or(DEFAULT_LOG_PAGE, |l| (l as usize).clamp(1, MAX_LOG_PAGE));
+    // Entries are appended with increasing `seq`, so the index is the count below the cursor.
+    let end = cursor.map_or(log.len(), |cursor| log.partition_point(|entry| entry.seq < cursor));
+    let start = end.saturating_sub(limit);
+    AdminLogPage {
+        entries: log[start..end].iter().rev().cloned().collect(),
+        next_cursor: (start > 0).then(|| log[start].seq),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_page_walks_backwards() {
+        let log: Vec<AdminLogEntry> = (1..=5)
+            .map(|seq| AdminLogEntry {
+                seq,
+                admin: Principal::anonymous(),
+                at: seq,
+                action: AdminAction::UnsuspendPrincipal {
+                    principal: Principal::anonymous(),
+                },
+            })
+            .collect();
+
+        let first = log_page(&log, None, Some(2));
+        assert_eq!(first.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![5, 4]);
+        assert_eq!(first.next_cursor, Some(4));
+
+        let last = log_page(&log, Some(2), Some(2));
+        assert_eq!(last.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(last.next_cursor, None);
+    }
+}
@@ -0,0 +1,471 @@
+// --- Audit Log ---
+// Every note mutation appends an entry whose hash covers the previous entry's
+// hash, so editing or dropping any entry breaks every hash after it.
+//
+// Audited: creating, updating and removing notes (including imports, batches,
+// encrypted content, checklist items and properties) and sharing encrypted
+// notes. Not audited: data kept beside a note rather than in it, namely
+// reminders, comments, templates and each viewer's pinned/favorite/archived/
+// color state.
+//
+// Entries live in stable memory as fixed-size records and are never copied
+// through the heap on upgrade:
+//   [0, HEADER_BYTES)   magic, entry count, offset and length of saved state
+//   [HEADER_BYTES, ..)  one RECORD_BYTES record per entry, in `seq` order
+// `pre_upgrade` writes the rest of the canister state right after the last
+// record; once it has been restored, new records overwrite it.
+
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::stable::{StableMemory, WASM_PAGE_SIZE_IN_BYTES};
+use sha2::{Digest, Sha256};
+
+// --- Constants ---
+const DEFAULT_PAGE: usize = 50;
+const MAX_PAGE: usize = 200;
+/// Entries looked at by one page request, matching or not.
+const MAX_SCAN: u64 = 10_000;
+const DEFAULT_VERIFY: u64 = 1_000;
+const MAX_VERIFY: u64 = 5_000;
+/// `prev_hash` of the first entry.
+const GENESIS_HASH: [u8; 32] = [0; 32];
+
+const MAGIC: &[u8; 8] = b"NOTEAUD1";
+const HEADER_BYTES: u64 = 64;
+const COUNT_OFFSET: u64 = 8;
+const SAVED_STATE_OFFSET: u64 = 16;
+const RECORD_BYTES: u64 = 256;
+const ACTIONS: [AuditAction; 7] = [
+    AuditAction::Create,
+    AuditAction::Update,
+    AuditAction::Delete,
+    AuditAction::ForceDelete,
+    AuditAction::Expire,
+    AuditAction::Share,
+    AuditAction::Unshare,
+];
+
+/// Stable memory in the canister; a heap buffer in native tests, where the
+/// stable memory system API is not available.
+#[cfg(not(test))]
+pub type LogMemory = ic_cdk::api::stable::CanisterStableMemory;
+#[cfg(test)]
+pub type LogMemory = tests::HeapMemory;
+
+// --- Data Structures ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    /// Removed by an admin with `force_delete_note`.
+    ForceDelete,
+    /// An ephemeral note removed by the expiry sweeper.
+    Expire,
+    /// A wrapped key for `recipient` was added or replaced.
+    Share,
+    /// `recipient`'s wrapped key was removed.
+    Unshare,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub caller: Principal,
+    pub action: AuditAction,
+    pub note_id: u64,
+    pub timestamp: u64,
+    /// SHA-256 of the note content (its ciphertext, or its checklist items or
+    /// properties as JSON) after the change; `None` for removals.
+    pub content_hash: Option<Vec<u8>>,
+    /// Whom a `Share` or `Unshare` was about.
+    pub recipient: Option<Principal>,
+    pub prev_hash: Vec<u8>,
+    pub hash: Vec<u8>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `cursor` to continue with older entries; `None` once the start
+    /// is reached. A page may hold fewer matches than asked for, or none.
+    pub next_cursor: Option<u64>,
+}
+
+/// The last entry a previous `verify_audit_chain` call checked.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct AuditCheckpoint {
+    pub seq: u64,
+    pub hash: Vec<u8>,
+}
+
+/// Result of a successful `verify_audit_chain`.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct AuditChainHead {
+    /// Entries verified from the start of the log, including earlier calls.
+    pub length: u64,
+    pub head_hash: Vec<u8>,
+    /// Whether `length` reaches the end of the log.
+    pub complete: bool,
+}
+
+pub struct AuditLog<M: StableMemory> {
+    memory: M,
+    len: u64,
+    head_hash: Vec<u8>,
+}
+
+pub fn content_hash(content: &[u8]) -> Vec<u8> {
+    Sha256::digest(content).to_vec()
+}
+
+/// Hash over every field but `hash`, with fixed-width or length-prefixed
+/// encodings so that no two entries serialize to the same bytes.
+fn entry_hash(entry: &AuditEntry) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(&entry.prev_hash);
+    hasher.update(entry.seq.to_be_bytes());
+    let caller = entry.caller.as_slice();
+    hasher.update([caller.len() as u8]);
+    hasher.update(caller);
+    hasher.update([entry.action as u8]);
+    hasher.update(entry.note_id.to_be_bytes());
+    hasher.update(entry.timestamp.to_be_bytes());
+    match &entry.content_hash {
+        Some(hash) => {
+            hasher.update([1]);
+            hasher.update(hash);
+        }
+        None => hasher.update([0]),
+    }
+    // Absent for every other action, so their hashes are unchanged.
+    if let Some(recipient) = &entry.recipient {
+        let recipient = recipient.as_slice();
+        hasher.update([recipient.len() as u8]);
+        hasher.update(recipient);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Record layout: seq, caller (length-prefixed), action, note id, timestamp,
+/// content hash (flag-prefixed), previous hash, hash, recipient (flag- and
+/// length-prefixed). The rest is reserved.
+fn encode_record(entry: &AuditEntry) -> [u8; RECORD_BYTES as usize] {
+    let mut record = [0; RECORD_BYTES as usize];
+    record[0..8].copy_from_slice(&entry.seq.to_be_bytes());
+    let caller = entry.caller.as_slice();
+    record[8] = caller.len() as u8;
+    record[9..9 + caller.len()].copy_from_slice(caller);
+    record[38] = entry.action as u8;
+    record[39..47].copy_from_slice(&entry.note_id.to_be_bytes());
+    record[47..55].copy_from_slice(&entry.timestamp.to_be_bytes());
+    if let Some(hash) = &entry.content_hash {
+        record[55] = 1;
+        record[56..88].copy_from_slice(hash);
+    }
+    record[88..120].copy_from_slice(&entry.prev_hash);
+    record[120..152].copy_from_slice(&entry.hash);
+    if let Some(recipient) = &entry.recipient {
+        let recipient = recipient.as_slice();
+        record[152] = 1;
+        record[153] = recipient.len() as u8;
+        record[154..154 + recipient.len()].copy_from_slice(recipient);
+    }
+    record
+}
+
+fn decode_record(record: &[u8]) -> Option<AuditEntry> {
+    let u64_at = |start: usize| u64::from_be_bytes(record[start..start + 8].try_into().expect("8 bytes"));
+    let caller_len = record[8] as usize;
+    let recipient_len = record[153] as usize;
+    if caller_len > 29 || record[55] > 1 || record[152] > 1 || recipient_len > 29 {
+        return None;
+    }
+    Some(AuditEntry {
+        seq: u64_at(0),
+        caller: Principal::from_slice(&record[9..9 + caller_len]),
+        action: *ACTIONS.get(record[38] as usize)?,
+        note_id: u64_at(39),
+        timestamp: u64_at(47),
+        content_hash: (record[55] == 1).then(|| record[56..88].to_vec()),
+        recipient: (record[152] == 1).then(|| Principal::from_slice(&record[154..154 + recipient_len])),
+        prev_hash: record[88..120].to_vec(),
+        hash: record[120..152].to_vec(),
+    })
+}
+
+impl<M: StableMemory> AuditLog<M> {
+    /// An empty log; nothing is written until the first append.
+    pub fn new(memory: M) -> Self {
+        AuditLog {
+            memory,
+            len: 0,
+            head_hash: GENESIS_HASH.to_vec(),
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Picks up the log written by the previous version. Returns `false` when
+    /// stable memory holds no log, e.g. a state saved before the log moved there.
+    pub fn load(&mut self) -> bool {
+        if self.memory.stable_size() == 0 {
+            return false;
+        }
+        let mut magic = [0; 8];
+        self.memory.stable_read(0, &mut magic);
+        if &magic != MAGIC {
+            return false;
+        }
+        self.len = self.read_u64(COUNT_OFFSET);
+        self.head_hash = match self.len {
+            0 => GENESIS_HASH.to_vec(),
+            len => self.get(len).map_or_else(|| GENESIS_HASH.to_vec(), |last| last.hash),
+        };
+        true
+    }
+
+    /// Replaces the whole log with `entries` as they are, hashes included.
+    pub fn replace_all(&mut self, entries: &[AuditEntry]) {
+        self.len = 0;
+        self.head_hash = GENESIS_HASH.to_vec();
+        self.set_saved_state(0, 0);
+        for entry in entries {
+            self.push(entry);
+        }
+    }
+
+    /// First byte after the last record, where `pre_upgrade` saves the state.
+    pub fn end_offset(&self) -> u64 {
+        HEADER_BYTES + self.len * RECORD_BYTES
+    }
+
+    pub fn set_saved_state(&mut self, offset: u64, len: u64) {
+        self.write_header();
+        let mut region = [0; 16];
+        region[..8].copy_from_slice(&offset.to_be_bytes());
+        region[8..].copy_from_slice(&len.to_be_bytes());
+        self.write(SAVED_STATE_OFFSET, &region);
+    }
+
+    /// The `(offset, length)` of the state saved by `pre_upgrade`, cleared so
+    /// that a later failed save is not mistaken for it.
+    pub fn take_saved_state(&mut self) -> Option<(u64, u64)> {
+        let region = (self.read_u64(SAVED_STATE_OFFSET), self.read_u64(SAVED_STATE_OFFSET + 8));
+        self.set_saved_state(0, 0);
+        (region.1 > 0).then_some(region)
+    }
+
+    pub fn get(&self, seq: u64) -> Option<AuditEntry> {
+        if seq == 0 || seq > self.len {
+            return None;
+        }
+        let mut record = [0; RECORD_BYTES as usize];
+        self.memory.stable_read(HEADER_BYTES + (seq - 1) * RECORD_BYTES, &mut record);
+        decode_record(&record)
+    }
+
+    /// Appends a new entry chained to the last one.
+    pub fn append(
+        &mut self,
+        caller: Principal,
+        action: AuditAction,
+        note_id: u64,
+        timestamp: u64,
+        content_hash: Option<Vec<u8>>,
+        recipient: Option<Principal>,
+    ) {
+        let mut entry = AuditEntry {
+            seq: self.len + 1,
+            caller,
+            action,
+            note_id,
+            timestamp,
+            content_hash,
+            recipient,
+            prev_hash: self.head_hash.clone(),
+            hash: Vec::new(),
+        };
+        entry.hash = entry_hash(&entry);
+        self.push(&entry);
+    }
+
+    /// Checks up to `limit` entries after `from`, or from the start, and
+    /// returns where to continue.
+    pub fn verify(&self, from: Option<AuditCheckpoint>, limit: Option<u32>) -> Result<AuditChainHead, String> {
+        let limit = limit.map_or(DEFAULT_VERIFY, |l| (l as u64).clamp(1, MAX_VERIFY));
+        let (mut seq, mut prev_hash) = from.map_or((0, GENESIS_HASH.to_vec()), |from| (from.seq, from.hash));
+        if seq > self.len {
+            return Err("The checkpoint is past the end of the audit log.".to_string());
+        }
+        let end = (seq + limit).min(self.len);
+        while seq < end {
+            seq += 1;
+            let entry = self.get(seq).ok_or_else(|| format!("Audit entry {} is unreadable.", seq))?;
+            if entry.seq != seq {
+                return Err(format!("Audit entry {} is out of sequence.", seq));
+            }
+            if entry.prev_hash != prev_hash {
+                return Err(format!("Audit entry {} does not link to the previous entry.", seq));
+            }
+            if entry.hash != entry_hash(&entry) {
+                return Err(format!("Audit entry {} does not match its hash.", seq));
+            }
+            prev_hash = entry.hash;
+        }
+        Ok(AuditChainHead {
+            length: seq,
+            head_hash: prev_hash,
+            complete: seq == self.len,
+        })
+    }
+
+    /// Newest-first page of the entries matching `filter`. `cursor` is the
+    /// `seq` to start below; at most `MAX_SCAN` entries are looked at.
+    pub fn page(&self, filter: impl Fn(&AuditEntry) -> bool, cursor: Option<u64>, limit: Option<u32>) -> AuditPage {
+        let limit = limit.map_or(DEFAULT_PAGE, |l| (l as usize).clamp(1, MAX_PAGE));
+        let mut seq = cursor.map_or(self.len, |cursor| cursor.saturating_sub(1).min(self.len));
+        let stop = seq.saturating_sub(MAX_SCAN);
+        let mut entries = Vec::new();
+        while seq > stop && entries.len() < limit {
+            entries.extend(self.get(seq).filter(|entry| filter(entry)));
+            seq -= 1;
+        }
+        AuditPage {
+            entries,
+            next_cursor: (seq > 0).then_some(seq + 1),
+        }
+    }
+
+    fn push(&mut self, entry: &AuditEntry) {
+        self.write(self.end_offset(), &encode_record(entry));
+        self.len += 1;
+        self.head_hash = entry.hash.clone();
+        self.write_header();
+    }
+
+    fn write_header(&mut self) {
+        let mut header = [0; 16];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..].copy_from_slice(&self.len.to_be_bytes());
+        self.write(0, &header);
+    }
+
+    fn read_u64(&self, offset: u64) -> u64 {
+        let mut bytes = [0; 8];
+        self.memory.stable_read(offset, &mut bytes);
+        u64::from_be_bytes(bytes)
+    }
+
+    /// Grows stable memory as needed. Running out of it traps, which rolls
+    /// back the change being audited.
+    fn write(&mut self, offset: u64, bytes: &[u8]) {
+        let pages = (offset + bytes.len() as u64).div_ceil(WASM_PAGE_SIZE_IN_BYTES);
+        let current = self.memory.stable_size();
+        if pages > current {
+            self.memory.stable_grow(pages - current).expect("stable memory exhausted");
+        }
+        self.memory.stable_write(offset, bytes);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use ic_cdk::api::stable::StableMemoryError;
+
+    /// Stable memory backed by a shared heap buffer.
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct HeapMemory(Rc<RefCell<Vec<u8>>>);
+
+    impl StableMemory for HeapMemory {
+        fn stable_size(&self) -> u64 {
+            self.0.borrow().len() as u64 / WASM_PAGE_SIZE_IN_BYTES
+        }
+
+        fn stable_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+            let old = self.stable_size();
+            self.0.borrow_mut().resize(((old + new_pages) * WASM_PAGE_SIZE_IN_BYTES) as usize, 0);
+            Ok(old)
+        }
+
+        fn stable_write(&self, offset: u64, buf: &[u8]) {
+            self.0.borrow_mut()[offset as usize..offset as usize + buf.len()].copy_from_slice(buf);
+        }
+
+        fn stable_read(&self, offset: u64, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.borrow()[offset as usize..offset as usize + buf.len()]);
+        }
+    }
+
+    fn sample_log(memory: HeapMemory) -> AuditLog<HeapMemory> {
+        let mut log = AuditLog::new(memory);
+        let alice = Principal::from_slice(&[1; 29]);
+        let bob = Principal::from_slice(&[2; 29]);
+        log.append(alice, AuditAction::Create, 1, 10, Some(content_hash(b"a")), None);
+        log.append(bob, AuditAction::Create, 2, 11, Some(content_hash(b"b")), None);
+        log.append(alice, AuditAction::Update, 1, 12, Some(content_hash(b"a2")), None);
+        log.append(alice, AuditAction::Delete, 1, 13, None, None);
+        log
+    }
+
+    #[test]
+    fn test_verify_in_ranges_and_detect_tampering() {
+        let memory = HeapMemory::default();
+        let log = sample_log(memory.clone());
+        let first = log.verify(None, Some(3)).unwrap();
+        assert_eq!((first.length, first.complete), (3, false));
+        let checkpoint = AuditCheckpoint { seq: first.length, hash: first.head_hash };
+        let head = log.verify(Some(checkpoint), None).unwrap();
+        assert_eq!((head.length, head.complete), (4, true));
+        assert_eq!(head.head_hash, log.get(4).unwrap().hash);
+
+        // Rewrite the timestamp of entry 2 in place.
+        let timestamp_offset = HEADER_BYTES + RECORD_BYTES + 47;
+        memory.stable_write(timestamp_offset, &99u64.to_be_bytes());
+        assert_eq!(log.verify(None, None).unwrap_err(), "Audit entry 2 does not match its hash.");
+        let forged = AuditCheckpoint { seq: 2, hash: vec![1; 32] };
+        assert!(log.verify(Some(forged), None).is_err());
+    }
+
+    #[test]
+    fn test_log_survives_reload_with_saved_state() {
+        let memory = HeapMemory::default();
+        let mut log = sample_log(memory.clone());
+        let end = log.end_offset();
+        log.set_saved_state(end, 100);
+
+        let mut reloaded = AuditLog::new(memory.clone());
+        assert!(reloaded.load());
+        assert_eq!(reloaded.len(), 4);
+        assert_eq!(reloaded.take_saved_state(), Some((end, 100)));
+        assert_eq!(reloaded.take_saved_state(), None);
+        reloaded.append(Principal::anonymous(), AuditAction::Share, 2, 14, None, Some(Principal::anonymous()));
+        assert_eq!(reloaded.verify(None, None).unwrap().length, 5);
+        assert_eq!(reloaded.get(5).unwrap().recipient, Some(Principal::anonymous()));
+
+        let migrated: Vec<AuditEntry> = (1..=5).filter_map(|seq| reloaded.get(seq)).collect();
+        let mut fresh = AuditLog::new(HeapMemory::default());
+        assert!(!fresh.load());
+        fresh.replace_all(&migrated);
+        assert_eq!(fresh.verify(None, None).unwrap().head_hash, migrated[4].hash);
+    }
+
+    #[test]
+    fn test_page_filters_newest_first() {
+        let log = sample_log(HeapMemory::default());
+        let for_note = |entry: &AuditEntry| entry.note_id == 1;
+
+        let first = log.page(for_note, None, Some(2));
+        assert_eq!(first.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![4, 3]);
+        assert_eq!(first.next_cursor, Some(3));
+
+        let rest = log.page(for_note, first.next_cursor, Some(2));
+        assert_eq!(rest.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(rest.next_cursor, None);
+    }
+}
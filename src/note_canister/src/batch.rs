@@ -0,0 +1,86 @@
+use std::collections::HashSet;
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::ratelimit::RateLimitError;
+use crate::{validate_note, NoteStore};
+
+// --- Constants ---
+pub const MAX_BATCH_OPS: usize = 100;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum BatchOp {
+    Create { title: String, content: String },
+    Update { id: u64, title: String, content: String },
+    Delete { id: u64 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum BatchOpResult {
+    Created(u64),
+    Updated(u64),
+    Deleted(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct BatchOpError {
+    pub index: u32,
+    pub message: String,
+}
+
+/// Why a batch was refused. Nothing is applied in any case.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum BatchError {
+    /// The batch has more than `max` operations.
+    TooManyOps { max: u32 },
+    RateLimited(RateLimitError),
+    /// The caller cannot write at all right now, e.g. while suspended.
+    Rejected(String),
+    /// Every operation that would fail, by index.
+    InvalidOps(Vec<BatchOpError>),
+}
+
+/// Checks every operation against the current store, in order, as if the
+/// earlier operations had already been applied. Returns all failures.
+pub fn validate_ops(notes: &NoteStore, caller: Principal, ops: &[BatchOp]) -> Vec<BatchOpError> {
+    let mut deleted: HashSet<u64> = HashSet::new();
+    let mut errors = Vec::new();
+
+    for (index, op) in ops.iter().enumerate() {
+        let result = match op {
+            BatchOp::Create { title, content } => validate_note(title, content),
+            BatchOp::Update { id, title, content } => validate_note(title, content)
+                .and_then(|_| check_target(notes, &deleted, caller, *id, "update")),
+            BatchOp::Delete { id } => check_target(notes, &deleted, caller, *id, "delete").map(|_| {
+                deleted.insert(*id);
+            }),
+        };
+        if let Err(message) = result {
+            errors.push(BatchOpError {
+                index: index as u32,
+                message,
+            });
+        }
+    }
+
+    errors
+}
+
+fn check_target(
+    notes: &NoteStore,
+    deleted: &HashSet<u64>,
+    caller: Principal,
+    id: u64,
+    action: &str,
+) -> Result<(), String> {
+    match notes.get(&id) {
+        Some(_) if deleted.contains(&id) => Err("Note is deleted earlier in this batch.".to_string()),
+        Some(note) if note.owner != caller => Err(format!("You can only {} your own notes.", action)),
+        Some(note) if note.encrypted.is_some() && action == "update" => {
+            Err("Encrypted notes can only be changed with update_encrypted_note.".to_string())
+        }
+        Some(_) => Ok(()),
+        None => Err("Note not found.".to_string()),
+    }
+}
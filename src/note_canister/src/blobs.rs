@@ -0,0 +1,83 @@
+// --- Content-Addressed Blob Store ---
+// Note bodies are stored once per distinct SHA-256 and shared by every note
+// with the same content. Each blob counts its references and is dropped when
+// the last note using it changes or is deleted.
+
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize};
+
+use crate::audit::content_hash;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+struct Blob {
+    data: Vec<u8>,
+    refs: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct BlobStore {
+    blobs: HashMap<Vec<u8>, Blob>,
+}
+
+impl BlobStore {
+    /// Adds a reference to `data`, storing it if it is new, and returns its hash.
+    pub fn put(&mut self, data: &[u8]) -> Vec<u8> {
+        let hash = content_hash(data);
+        self.blobs
+            .entry(hash.clone())
+            .or_insert_with(|| Blob {
+                data: data.to_vec(),
+                refs: 0,
+            })
+            .refs += 1;
+        hash
+    }
+
+    /// Drops one reference to `hash`, removing the blob when none are left.
+    pub fn release(&mut self, hash: &[u8]) {
+        if let Some(blob) = self.blobs.get_mut(hash) {
+            blob.refs -= 1;
+            if blob.refs == 0 {
+                self.blobs.remove(hash);
+            }
+        }
+    }
+
+    pub fn get(&self, hash: &[u8]) -> Option<&[u8]> {
+        self.blobs.get(hash).map(|blob| blob.data.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.blobs.len()
+    }
+
+    /// Bytes actually held, counting shared content once.
+    pub fn stored_bytes(&self) -> u64 {
+        self.blobs.values().map(|blob| blob.data.len() as u64).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_content_is_stored_once() {
+        let mut store = BlobStore::default();
+        let first = store.put(b"same body");
+        let second = store.put(b"same body");
+        let other = store.put(b"other body");
+
+        assert_eq!(first, second);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.stored_bytes(), 19);
+
+        store.release(&first);
+        assert_eq!(store.get(&first), Some(&b"same body"[..]));
+        store.release(&second);
+        assert_eq!(store.get(&first), None);
+        assert_eq!(store.get(&other), Some(&b"other body"[..]));
+    }
+}
@@ -0,0 +1,140 @@
+// --- Checklists ---
+// A checklist note keeps structured items next to its content, which serves
+// as the list's description. Items are changed one at a time so clients never
+// have to rewrite the whole note to tick something off.
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+pub const MAX_CHECKLIST_ITEMS: usize = 200;
+const MAX_ITEM_TEXT_CHARS: usize = 500;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct ChecklistItem {
+    /// Unique within its note; never reused after an item is removed.
+    pub id: u64,
+    pub text: String,
+    pub done: bool,
+    pub due_at: Option<u64>,
+    pub assignee: Option<Principal>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Checklist {
+    pub items: Vec<ChecklistItem>,
+    /// Highest item id handed out so far.
+    pub last_item_id: u64,
+}
+
+/// An item to add; it starts out not done.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct NewChecklistItem {
+    pub text: String,
+    pub due_at: Option<u64>,
+    pub assignee: Option<Principal>,
+}
+
+/// Entry of `get_my_open_tasks`.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct OpenTask {
+    pub note_id: u64,
+    pub note_title: String,
+    pub item: ChecklistItem,
+}
+
+impl Checklist {
+    pub fn bytes(&self) -> u64 {
+        self.items.iter().map(|item| item.text.len() as u64).sum()
+    }
+
+    /// Appends `item` and returns its id.
+    pub fn add_item(&mut self, item: NewChecklistItem) -> Result<u64, String> {
+        let text = item.text.trim();
+        if text.is_empty() {
+            return Err("Checklist item text cannot be empty.".to_string());
+        }
+        if text.chars().count() > MAX_ITEM_TEXT_CHARS {
+            return Err(format!("Checklist item text exceeds {} characters.", MAX_ITEM_TEXT_CHARS));
+        }
+        if self.items.len() >= MAX_CHECKLIST_ITEMS {
+            return Err(format!("A checklist can have at most {} items.", MAX_CHECKLIST_ITEMS));
+        }
+        self.last_item_id += 1;
+        self.items.push(ChecklistItem {
+            id: self.last_item_id,
+            text: text.to_string(),
+            done: false,
+            due_at: item.due_at,
+            assignee: item.assignee,
+        });
+        Ok(self.last_item_id)
+    }
+
+    pub fn item_mut(&mut self, item_id: u64) -> Result<&mut ChecklistItem, String> {
+        self.items
+            .iter_mut()
+            .find(|item| item.id == item_id)
+            .ok_or_else(|| "Checklist item not found.".to_string())
+    }
+
+    pub fn remove_item(&mut self, item_id: u64) -> Result<(), String> {
+        self.item_mut(item_id)?;
+        self.items.retain(|item| item.id != item_id);
+        Ok(())
+    }
+
+    /// Puts the items in the order of `order`, which must list every item id once.
+    pub fn reorder(&mut self, order: &[u64]) -> Result<(), String> {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let complete = sorted.len() == order.len()
+            && order.len() == self.items.len()
+            && order.iter().all(|id| self.items.iter().any(|item| item.id == *id));
+        if !complete {
+            return Err("The new order must list every checklist item exactly once.".to_string());
+        }
+        self.items.sort_by_key(|item| order.iter().position(|id| *id == item.id));
+        Ok(())
+    }
+}
+
+/// Sorts open tasks soonest due first; tasks without a due date go last.
+pub fn sort_open_tasks(tasks: &mut [OpenTask]) {
+    tasks.sort_by_key(|task| (task.item.due_at.is_none(), task.item.due_at, task.note_id, task.item.id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_item(text: &str) -> NewChecklistItem {
+        NewChecklistItem {
+            text: text.to_string(),
+            due_at: None,
+            assignee: None,
+        }
+    }
+
+    #[test]
+    fn test_items_keep_ids_and_reorder() {
+        let mut checklist = Checklist::default();
+        let milk = checklist.add_item(new_item(" Milk ")).unwrap();
+        let eggs = checklist.add_item(new_item("Eggs")).unwrap();
+        assert!(checklist.add_item(new_item("  ")).is_err());
+        assert_eq!(checklist.items[0].text, "Milk");
+
+        checklist.remove_item(eggs).unwrap();
+        let bread = checklist.add_item(new_item("Bread")).unwrap();
+        assert_eq!(bread, eggs + 1);
+
+        assert!(checklist.reorder(&[bread]).is_err());
+        assert!(checklist.reorder(&[bread, bread]).is_err());
+        checklist.reorder(&[bread, milk]).unwrap();
+        assert_eq!(checklist.items.iter().map(|item| item.id).collect::<Vec<_>>(), vec![bread, milk]);
+
+        checklist.item_mut(milk).unwrap().done = true;
+        assert!(checklist.remove_item(99).is_err());
+    }
+}
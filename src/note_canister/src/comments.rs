@@ -0,0 +1,160 @@
+// --- Comment Threads ---
+// Comments hang off a note and may reply to another comment on the same note,
+// so each note's comments form a forest. Deleting a comment that has replies
+// leaves a tombstone in its place to keep the thread intact.
+
+use std::collections::BTreeMap;
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+pub const MAX_COMMENT_BYTES: usize = 1024;
+pub const MAX_COMMENTS_PER_NOTE: usize = 500;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Comment {
+    pub id: u64,
+    pub note_id: u64,
+    pub author: Principal,
+    /// The comment this one replies to; `None` for a top-level comment.
+    pub parent_id: Option<u64>,
+    pub body: String,
+    pub created_at: u64,
+    pub updated_at: Option<u64>,
+    /// Set when a comment with replies is deleted; its body is cleared.
+    pub deleted: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Comments {
+    last_id: u64,
+    comments: BTreeMap<u64, Comment>,
+}
+
+pub fn validate_body(body: &str) -> Result<String, String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err("Comment cannot be empty.".to_string());
+    }
+    if body.len() > MAX_COMMENT_BYTES {
+        return Err(format!("Comment exceeds {} byte limit.", MAX_COMMENT_BYTES));
+    }
+    Ok(body.to_string())
+}
+
+impl Comments {
+    pub fn get(&self, id: u64) -> Option<&Comment> {
+        self.comments.get(&id)
+    }
+
+    /// Adds a comment to `note_id` and returns its id. A `parent_id` must name
+    /// a live comment on the same note.
+    pub fn add(
+        &mut self,
+        note_id: u64,
+        author: Principal,
+        parent_id: Option<u64>,
+        body: &str,
+        now: u64,
+    ) -> Result<u64, String> {
+        let body = validate_body(body)?;
+        if let Some(parent_id) = parent_id {
+            match self.comments.get(&parent_id) {
+                Some(parent) if parent.note_id == note_id && !parent.deleted => {}
+                _ => return Err("Parent comment not found.".to_string()),
+            }
+        }
+        if self.comments.values().filter(|comment| comment.note_id == note_id).count() >= MAX_COMMENTS_PER_NOTE {
+            return Err(format!("A note can have at most {} comments.", MAX_COMMENTS_PER_NOTE));
+        }
+        self.last_id += 1;
+        self.comments.insert(
+            self.last_id,
+            Comment {
+                id: self.last_id,
+                note_id,
+                author,
+                parent_id,
+                body,
+                created_at: now,
+                updated_at: None,
+                deleted: false,
+            },
+        );
+        Ok(self.last_id)
+    }
+
+    pub fn edit(&mut self, id: u64, author: Principal, body: &str, now: u64) -> Result<(), String> {
+        let body = validate_body(body)?;
+        let comment = self.authored_mut(id, author, "edit")?;
+        comment.body = body;
+        comment.updated_at = Some(now);
+        Ok(())
+    }
+
+    pub fn delete(&mut self, id: u64, author: Principal, now: u64) -> Result<(), String> {
+        self.authored_mut(id, author, "delete")?;
+        if self.comments.values().any(|comment| comment.parent_id == Some(id)) {
+            let comment = self.comments.get_mut(&id).expect("checked above");
+            comment.body.clear();
+            comment.deleted = true;
+            comment.updated_at = Some(now);
+        } else {
+            self.comments.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn authored_mut(&mut self, id: u64, author: Principal, verb: &str) -> Result<&mut Comment, String> {
+        match self.comments.get_mut(&id) {
+            Some(comment) if comment.deleted => Err("Comment not found.".to_string()),
+            Some(comment) if comment.author != author => Err(format!("You can only {} your own comments.", verb)),
+            Some(comment) => Ok(comment),
+            None => Err("Comment not found.".to_string()),
+        }
+    }
+
+    /// Every comment on `note_id` in the order they were posted.
+    pub fn for_note(&self, note_id: u64) -> Vec<Comment> {
+        self.comments.values().filter(|comment| comment.note_id == note_id).cloned().collect()
+    }
+
+    pub fn remove_note(&mut self, note_id: u64) {
+        self.comments.retain(|_, comment| comment.note_id != note_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threads_keep_tombstones_for_replied_comments() {
+        let alice = Principal::from_slice(&[1; 29]);
+        let bob = Principal::from_slice(&[2; 29]);
+        let mut comments = Comments::default();
+
+        let root = comments.add(1, alice, None, " First ", 10).unwrap();
+        let reply = comments.add(1, bob, Some(root), "Reply", 11).unwrap();
+        let other = comments.add(2, bob, None, "Elsewhere", 12).unwrap();
+        assert_eq!(comments.add(1, bob, Some(other), "Wrong note", 13).unwrap_err(), "Parent comment not found.");
+        assert!(comments.add(1, bob, None, "   ", 13).is_err());
+
+        assert_eq!(comments.edit(root, bob, "Hijack", 14).unwrap_err(), "You can only edit your own comments.");
+        comments.edit(root, alice, "First, edited", 14).unwrap();
+
+        comments.delete(root, alice, 15).unwrap();
+        let thread = comments.for_note(1);
+        assert_eq!(thread.len(), 2);
+        assert!(thread[0].deleted && thread[0].body.is_empty());
+        assert_eq!(thread[1].parent_id, Some(root));
+        assert!(comments.add(1, bob, Some(root), "To a tombstone", 16).is_err());
+
+        comments.delete(reply, bob, 16).unwrap();
+        assert_eq!(comments.for_note(1).len(), 1);
+        comments.remove_note(1);
+        assert!(comments.for_note(1).is_empty());
+        assert_eq!(comments.for_note(2).len(), 1);
+    }
+}
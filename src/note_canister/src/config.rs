@@ -0,0 +1,241 @@
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Defaults ---
+const DEFAULT_ANONYMOUS_MAX_NOTES: u64 = 100;
+const DEFAULT_QUOTA_MAX_NOTES: u64 = 1_000;
+const DEFAULT_QUOTA_MAX_BYTES: u64 = 2 * 1024 * 1024;
+const DEFAULT_VETKD_KEY_NAME: &str = "key_1";
+const DEFAULT_MAX_TITLE_CHARS: u32 = 200;
+const DEFAULT_MAX_LINES: u32 = 200;
+const DEFAULT_MAX_NOTE_SIZE_BYTES: u64 = 1024;
+/// Enough tokens for a full `batch_apply` or import batch in one call.
+const DEFAULT_RATE_LIMIT_BURST: u32 = 100;
+/// Keeps a note, with encoding overhead, well inside the 2 MiB ingress message limit.
+pub const MAX_NOTE_SIZE_LIMIT_BYTES: u64 = 1024 * 1024;
+
+// --- Data Structures ---
+/// How writes from the anonymous principal are treated. All anonymous callers
+/// share a single owner, so their notes form one global bucket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub enum AnonymousPolicy {
+    /// Anonymous callers cannot create, update or delete notes.
+    Disallow,
+    /// Anonymous callers may write, but the shared bucket holds at most `max_notes`.
+    AllowWithSeparateQuota { max_notes: u64 },
+    /// Anonymous notes are deleted automatically `ttl_seconds` after creation.
+    AllowEphemeral { ttl_seconds: u64 },
+}
+
+impl Default for AnonymousPolicy {
+    fn default() -> Self {
+        AnonymousPolicy::AllowWithSeparateQuota {
+            max_notes: DEFAULT_ANONYMOUS_MAX_NOTES,
+        }
+    }
+}
+
+/// Token bucket settings: up to `burst` calls at once, refilled at
+/// `refill_per_minute` calls per minute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub refill_per_minute: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct RateLimits {
+    pub create: RateLimit,
+    pub update: RateLimit,
+    pub delete: RateLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            create: RateLimit {
+                burst: DEFAULT_RATE_LIMIT_BURST,
+                refill_per_minute: 30,
+            },
+            update: RateLimit {
+                burst: DEFAULT_RATE_LIMIT_BURST,
+                refill_per_minute: 60,
+            },
+            delete: RateLimit {
+                burst: DEFAULT_RATE_LIMIT_BURST,
+                refill_per_minute: 60,
+            },
+        }
+    }
+}
+
+/// Storage allowance for one principal. Bytes count titles, contents and tags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Quota {
+    pub max_notes: u64,
+    pub max_bytes: u64,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Quota {
+            max_notes: DEFAULT_QUOTA_MAX_NOTES,
+            max_bytes: DEFAULT_QUOTA_MAX_BYTES,
+        }
+    }
+}
+
+/// How plaintext titles and content are cleaned up and checked on every write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct ValidationPolicy {
+    pub max_title_chars: u32,
+    pub max_lines: u32,
+    /// Store text in Unicode Normalization Form C.
+    pub normalize_nfc: bool,
+    /// Strip leading and trailing whitespace from the title and content.
+    pub trim_whitespace: bool,
+    /// Reject control characters other than newline and tab.
+    pub forbid_control_chars: bool,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        ValidationPolicy {
+            max_title_chars: DEFAULT_MAX_TITLE_CHARS,
+            max_lines: DEFAULT_MAX_LINES,
+            normalize_nfc: true,
+            trim_whitespace: true,
+            forbid_control_chars: true,
+        }
+    }
+}
+
+/// Where keys for encrypted notes are derived.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub enum KeyDerivation {
+    /// The subnet's threshold vetKD key with this name, e.g. "key_1" or "test_key_1".
+    VetKd { key_name: String },
+    /// Deterministic keys derived from the canister id. Anyone can recompute
+    /// them, so this is only for local replicas and tests.
+    InsecureLocal,
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        KeyDerivation::VetKd {
+            key_name: DEFAULT_VETKD_KEY_NAME.to_string(),
+        }
+    }
+}
+
+/// Runtime settings persisted across upgrades.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct CanisterConfig {
+    /// Limit on a plaintext note's title plus content, in UTF-8 bytes.
+    pub max_note_size_bytes: u64,
+    pub anonymous_policy: AnonymousPolicy,
+    pub rate_limits: RateLimits,
+    /// Applies to every principal without an override.
+    pub default_quota: Quota,
+    pub key_derivation: KeyDerivation,
+    pub validation: ValidationPolicy,
+}
+
+impl Default for CanisterConfig {
+    fn default() -> Self {
+        CanisterConfig {
+            max_note_size_bytes: DEFAULT_MAX_NOTE_SIZE_BYTES,
+            anonymous_policy: AnonymousPolicy::default(),
+            rate_limits: RateLimits::default(),
+            default_quota: Quota::default(),
+            key_derivation: KeyDerivation::default(),
+            validation: ValidationPolicy::default(),
+        }
+    }
+}
+
+/// Argument accepted by `init` and `post_upgrade`, also used to persist the
+/// configuration. Omitted fields keep their current (or default) value, so new
+/// settings can be added without breaking older arguments or snapshots.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, serde::Serialize)]
+pub struct ConfigArgs {
+    pub max_note_size_bytes: Option<u64>,
+    pub anonymous_policy: Option<AnonymousPolicy>,
+    pub rate_limits: Option<RateLimits>,
+    pub default_quota: Option<Quota>,
+    pub key_derivation: Option<KeyDerivation>,
+    pub validation: Option<ValidationPolicy>,
+    /// Added to the admin set at install or upgrade. Admins are kept with the
+    /// rest of the canister state, not in the configuration.
+    pub admins: Option<Vec<Principal>>,
+}
+
+impl ConfigArgs {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(max_bytes) = self.max_note_size_bytes {
+            if max_bytes == 0 || max_bytes > MAX_NOTE_SIZE_LIMIT_BYTES {
+                return Err(format!(
+                    "Max note size must be between 1 and {} bytes.",
+                    MAX_NOTE_SIZE_LIMIT_BYTES
+                ));
+            }
+        }
+        if let Some(limits) = &self.rate_limits {
+            for (method, limit) in [("create", limits.create), ("update", limits.update), ("delete", limits.delete)] {
+                if limit.burst == 0 || limit.refill_per_minute == 0 {
+                    return Err(format!(
+                        "Rate limit for {} must have a non-zero burst and refill rate.",
+                        method
+                    ));
+                }
+            }
+        }
+        if let Some(validation) = &self.validation {
+            if validation.max_title_chars == 0 || validation.max_lines == 0 {
+                return Err("Validation limits must be greater than zero.".to_string());
+            }
+        }
+        if let Some(KeyDerivation::VetKd { key_name }) = &self.key_derivation {
+            if key_name.trim().is_empty() {
+                return Err("vetKD key name cannot be empty.".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CanisterConfig {
+    pub fn apply(&mut self, args: ConfigArgs) {
+        if let Some(max_bytes) = args.max_note_size_bytes {
+            self.max_note_size_bytes = max_bytes;
+        }
+        if let Some(policy) = args.anonymous_policy {
+            self.anonymous_policy = policy;
+        }
+        if let Some(rate_limits) = args.rate_limits {
+            self.rate_limits = rate_limits;
+        }
+        if let Some(default_quota) = args.default_quota {
+            self.default_quota = default_quota;
+        }
+        if let Some(key_derivation) = args.key_derivation {
+            self.key_derivation = key_derivation;
+        }
+        if let Some(validation) = args.validation {
+            self.validation = validation;
+        }
+    }
+}
+
+impl From<&CanisterConfig> for ConfigArgs {
+    fn from(config: &CanisterConfig) -> Self {
+        ConfigArgs {
+            max_note_size_bytes: Some(config.max_note_size_bytes),
+            anonymous_policy: Some(config.anonymous_policy),
+            rate_limits: Some(config.rate_limits),
+            default_quota: Some(config.default_quota),
+            key_derivation: Some(config.key_derivation.clone()),
+            validation: Some(config.validation),
+            admins: None,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// --- CSV (RFC 4180) ---
+
+/// Formats one CSV record terminated by CRLF.
+pub fn write_row(fields: impl IntoIterator<Item = String>) -> String {
+    let escaped: Vec<String> = fields.into_iter().map(|field| escape_field(&field)).collect();
+    format!("{}\r\n", escaped.join(","))
+}
+
+/// Quotes a CSV field per RFC 4180 when it contains a delimiter, quote or newline.
+pub fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Parses CSV text into records. Quoted fields may span lines.
+pub fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut field_started = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                c => field.push(c),
+            }
+            continue;
+        }
+        match ch {
+            '"' if !field_started => {
+                in_quotes = true;
+                field_started = true;
+            }
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                field_started = false;
+            }
+            '\r' | '\n' => {
+                if ch == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                field_started = false;
+            }
+            c => {
+                field.push(c);
+                field_started = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err("Unterminated quoted field in CSV.".to_string());
+    }
+    if field_started || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let row = write_row(["plain".to_string(), "a, \"b\"\nc".to_string(), String::new()]);
+        assert_eq!(row, "plain,\"a, \"\"b\"\"\nc\",\r\n");
+        assert_eq!(
+            parse(&format!("h1,h2,h3\r\n{}", row)).unwrap(),
+            vec![
+                vec!["h1".to_string(), "h2".to_string(), "h3".to_string()],
+                vec!["plain".to_string(), "a, \"b\"\nc".to_string(), String::new()],
+            ]
+        );
+        assert!(parse("\"open").is_err());
+    }
+}
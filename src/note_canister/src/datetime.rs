@@ -0,0 +1,173 @@
+// --- Timestamp Formatting ---
+// Canister time is nanoseconds since the Unix epoch. Exported and imported
+// documents use RFC 3339 (UTC) so they stay readable outside the IC.
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Formats nanoseconds since the epoch as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_rfc3339(nanos: u64) -> String {
+    let total_seconds = nanos / NANOS_PER_SECOND;
+    let days = (total_seconds / SECONDS_PER_DAY) as i64;
+    let seconds_of_day = total_seconds % SECONDS_PER_DAY;
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        (seconds_of_day % 3600) / 60,
+        seconds_of_day % 60
+    )
+}
+
+/// Parses an RFC 3339 timestamp (or a bare `YYYY-MM-DD` date) into nanoseconds
+/// since the epoch. Fractional seconds and numeric offsets are honoured.
+/// Returns `None` for malformed input or dates before 1970.
+pub fn parse_rfc3339(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let (date, rest) = match input.find(['T', 't', ' ']) {
+        Some(idx) => (&input[..idx], &input[idx + 1..]),
+        None => (input, ""),
+    };
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = parse_digits(date_parts.next()?, 4)?;
+    let month: u32 = parse_digits(date_parts.next()?, 2)?;
+    let day: u32 = parse_digits(date_parts.next()?, 2)?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let mut seconds_of_day: i64 = 0;
+    let mut fraction_nanos: u64 = 0;
+    let mut offset_seconds: i64 = 0;
+
+    if !rest.is_empty() {
+        let (clock, zone) = split_zone(rest)?;
+        let mut clock_parts = clock.splitn(3, ':');
+        let hour: i64 = parse_digits(clock_parts.next()?, 2)?;
+        let minute: i64 = parse_digits(clock_parts.next()?, 2)?;
+        let (second, fraction) = match clock_parts.next() {
+            Some(sec) => match sec.split_once('.') {
+                Some((whole, frac)) => (parse_digits(whole, 2)?, Some(frac)),
+                None => (parse_digits(sec, 2)?, None),
+            },
+            None => (0, None),
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return None;
+        }
+        seconds_of_day = hour * 3600 + minute * 60 + second;
+
+        if let Some(frac) = fraction {
+            if frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let digits: String = frac.chars().take(9).collect();
+            let padded = format!("{:0<9}", digits);
+            fraction_nanos = padded.parse().ok()?;
+        }
+
+        offset_seconds = match zone {
+            "" | "Z" | "z" => 0,
+            _ => {
+                let sign = if zone.starts_with('-') { -1 } else { 1 };
+                let (h, m) = zone[1..].split_once(':').unwrap_or((&zone[1..], "00"));
+                let h: i64 = parse_digits(h, 2)?;
+                let m: i64 = parse_digits(m, 2)?;
+                sign * (h * 3600 + m * 60)
+            }
+        };
+    }
+
+    let days = days_from_civil(year, month, day);
+    let total_seconds = days * SECONDS_PER_DAY as i64 + seconds_of_day - offset_seconds;
+    if total_seconds < 0 {
+        return None;
+    }
+    (total_seconds as u64)
+        .checked_mul(NANOS_PER_SECOND)?
+        .checked_add(fraction_nanos)
+}
+
+fn split_zone(rest: &str) -> Option<(&str, &str)> {
+    if let Some(stripped) = rest.strip_suffix(['Z', 'z']) {
+        return Some((stripped, ""));
+    }
+    match rest.rfind(['+', '-']) {
+        Some(idx) => Some((&rest[..idx], &rest[idx..])),
+        None => Some((rest, "")),
+    }
+}
+
+fn parse_digits<T: std::str::FromStr>(s: &str, width: usize) -> Option<T> {
+    if s.len() != width || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's days <-> civil date algorithms.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1_700_000_000 * NANOS_PER_SECOND), "2023-11-14T22:13:20Z");
+        assert_eq!(format_rfc3339(951_782_400 * NANOS_PER_SECOND), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339("2023-11-14T22:13:20Z"), Some(1_700_000_000 * NANOS_PER_SECOND));
+        assert_eq!(parse_rfc3339("1970-01-02"), Some(SECONDS_PER_DAY * NANOS_PER_SECOND));
+        assert_eq!(
+            parse_rfc3339("2023-11-14T23:13:20.5+01:00"),
+            Some(1_700_000_000 * NANOS_PER_SECOND + 500_000_000)
+        );
+        assert_eq!(parse_rfc3339("2023-02-29"), None);
+        assert_eq!(parse_rfc3339("not a date"), None);
+        assert_eq!(parse_rfc3339("1969-12-31T23:59:59Z"), None);
+    }
+}
@@ -0,0 +1,137 @@
+// --- Client-Side Encrypted Notes ---
+// Clients encrypt the note with a random content key and wrap that key once per
+// recipient. The canister only stores the opaque bytes: it never sees the key
+// or the plaintext, so none of the plaintext checks apply to these notes.
+
+use std::collections::HashSet;
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+/// Room for cipher overhead and padding on top of the configured plaintext limit.
+pub const CIPHERTEXT_OVERHEAD_BYTES: usize = 1024;
+pub const MAX_RECIPIENTS: usize = 20;
+const MAX_WRAPPED_KEY_BYTES: usize = 512;
+const MAX_NONCE_BYTES: usize = 64;
+const MAX_LABEL_LENGTH: usize = 64;
+
+// --- Data Structures ---
+/// The content key of a note, encrypted for one recipient.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct WrappedKey {
+    pub recipient: Principal,
+    /// How `key` was wrapped, e.g. "vetkd-ibe" or "rsa-oaep-sha256".
+    pub scheme: String,
+    pub key: Vec<u8>,
+}
+
+/// Ciphertext of a note's title and content, with what a recipient needs to decrypt it.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct EncryptedContent {
+    /// Content cipher, e.g. "AES-256-GCM".
+    pub algorithm: String,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub wrapped_keys: Vec<WrappedKey>,
+}
+
+impl EncryptedContent {
+    pub fn has_recipient(&self, principal: Principal) -> bool {
+        self.wrapped_keys.iter().any(|key| key.recipient == principal)
+    }
+
+    /// Bytes stored for the note, counted against the owner's quota.
+    pub fn stored_bytes(&self) -> u64 {
+        let keys: usize = self
+            .wrapped_keys
+            .iter()
+            .map(|key| key.scheme.len() + key.key.len())
+            .sum();
+        (self.algorithm.len() + self.nonce.len() + self.ciphertext.len() + keys) as u64
+    }
+}
+
+/// Checks sizes and recipients only; the ciphertext itself is never inspected.
+pub fn validate_encrypted(owner: Principal, encrypted: &EncryptedContent, max_note_bytes: usize) -> Result<(), String> {
+    if encrypted.ciphertext.is_empty() {
+        return Err("Ciphertext cannot be empty.".to_string());
+    }
+    let max_ciphertext_bytes = max_note_bytes + CIPHERTEXT_OVERHEAD_BYTES;
+    if encrypted.ciphertext.len() > max_ciphertext_bytes {
+        return Err(format!("Ciphertext exceeds {} byte limit.", max_ciphertext_bytes));
+    }
+    validate_label("Algorithm", &encrypted.algorithm)?;
+    if encrypted.nonce.len() > MAX_NONCE_BYTES {
+        return Err(format!("Nonce exceeds {} byte limit.", MAX_NONCE_BYTES));
+    }
+    if encrypted.wrapped_keys.len() > MAX_RECIPIENTS {
+        return Err(format!("An encrypted note can have at most {} recipients.", MAX_RECIPIENTS));
+    }
+    let mut recipients = HashSet::new();
+    for key in &encrypted.wrapped_keys {
+        validate_wrapped_key(key)?;
+        if !recipients.insert(key.recipient) {
+            return Err(format!("Recipient {} has more than one wrapped key.", key.recipient));
+        }
+    }
+    if !recipients.contains(&owner) {
+        return Err("The owner must have a wrapped key for their own note.".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_wrapped_key(key: &WrappedKey) -> Result<(), String> {
+    if key.recipient == Principal::anonymous() {
+        return Err("The anonymous principal cannot be a recipient.".to_string());
+    }
+    validate_label("Key scheme", &key.scheme)?;
+    if key.key.is_empty() || key.key.len() > MAX_WRAPPED_KEY_BYTES {
+        return Err(format!("Wrapped keys must be between 1 and {} bytes.", MAX_WRAPPED_KEY_BYTES));
+    }
+    Ok(())
+}
+
+fn validate_label(name: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() || value.len() > MAX_LABEL_LENGTH {
+        return Err(format!("{} must be between 1 and {} bytes.", name, MAX_LABEL_LENGTH));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_for(recipient: Principal) -> WrappedKey {
+        WrappedKey {
+            recipient,
+            scheme: "rsa-oaep-sha256".to_string(),
+            key: vec![7; 32],
+        }
+    }
+
+    #[test]
+    fn test_validate_encrypted() {
+        let owner = Principal::from_slice(&[1; 29]);
+        let friend = Principal::from_slice(&[2; 29]);
+        let mut encrypted = EncryptedContent {
+            algorithm: "AES-256-GCM".to_string(),
+            nonce: vec![0; 12],
+            // Not valid UTF-8, and never checked as text.
+            ciphertext: vec![0xff, 0x00, 0xfe],
+            wrapped_keys: vec![key_for(owner), key_for(friend)],
+        };
+        assert!(validate_encrypted(owner, &encrypted, 1024).is_ok());
+        assert_eq!(
+            validate_encrypted(friend, &EncryptedContent { wrapped_keys: vec![key_for(owner)], ..encrypted.clone() }, 1024)
+                .unwrap_err(),
+            "The owner must have a wrapped key for their own note."
+        );
+
+        encrypted.wrapped_keys.push(key_for(friend));
+        assert!(validate_encrypted(owner, &encrypted, 1024).unwrap_err().contains("more than one wrapped key"));
+
+        encrypted.ciphertext = vec![0; 1024 + CIPHERTEXT_OVERHEAD_BYTES + 1];
+        assert!(validate_encrypted(owner, &encrypted, 1024).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+// --- Evernote ENEX Import ---
+// An ENEX file is an `<en-export>` of `<note>` elements whose `<content>` holds
+// an ENML document (restricted XHTML). ENML is converted to Markdown. The
+// canister has no attachment storage, so `<resource>` elements are listed by
+// name at the end of the note and `<en-media>` embeds become placeholders.
+
+use quick_xml::escape::resolve_html5_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::datetime::parse_rfc3339;
+use crate::import::NoteDraft;
+
+#[derive(Default)]
+struct EnexNote {
+    title: String,
+    content: String,
+    created: Option<String>,
+    updated: Option<String>,
+    tags: Vec<String>,
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Default)]
+struct Attachment {
+    file_name: Option<String>,
+    mime: Option<String>,
+}
+
+impl Attachment {
+    fn label(&self) -> String {
+        self.file_name
+            .clone()
+            .or_else(|| self.mime.clone())
+            .unwrap_or_else(|| "attachment".to_string())
+    }
+}
+
+/// Parses every `<note>` in an ENEX export into a draft.
+pub fn parse_enex(xml: &str) -> Result<Vec<NoteDraft>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut current: Option<EnexNote> = None;
+    let mut drafts = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid ENEX at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(start) => {
+                let name = element_name(&start);
+                match name.as_str() {
+                    "note" => current = Some(EnexNote::default()),
+                    "resource" => {
+                        if let Some(note) = current.as_mut() {
+                            note.attachments.push(Attachment::default());
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                let closed = path.pop();
+                if closed.as_deref() == Some("note") {
+                    drafts.extend(current.take().map(to_draft).transpose()?);
+                }
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape_with(resolve_html5_entity)
+                    .map_err(|e| format!("Invalid ENEX text: {}", e))?;
+                append_field(current.as_mut(), &path, &text);
+            }
+            Event::CData(data) => {
+                let text = String::from_utf8_lossy(&data);
+                append_field(current.as_mut(), &path, &text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(drafts)
+}
+
+fn append_field(note: Option<&mut EnexNote>, path: &[String], text: &str) {
+    let Some(note) = note else {
+        return;
+    };
+    let parent = path.len().checked_sub(2).and_then(|idx| path.get(idx)).map(String::as_str);
+    match (parent, path.last().map(String::as_str)) {
+        (Some("note"), Some("title")) => note.title.push_str(text),
+        (Some("note"), Some("content")) => note.content.push_str(text),
+        (Some("note"), Some("created")) => note.created.get_or_insert_with(String::new).push_str(text),
+        (Some("note"), Some("updated")) => note.updated.get_or_insert_with(String::new).push_str(text),
+        (Some("note"), Some("tag")) => note.tags.push(text.trim().to_string()),
+        (Some("resource"), Some("mime")) => {
+            if let Some(attachment) = note.attachments.last_mut() {
+                attachment.mime = Some(text.trim().to_string());
+            }
+        }
+        (Some("resource-attributes"), Some("file-name")) => {
+            if let Some(attachment) = note.attachments.last_mut() {
+                attachment.file_name = Some(text.trim().to_string());
+            }
+        }
+        _ => {}
+    }
+}
+
+fn to_draft(note: EnexNote) -> Result<NoteDraft, String> {
+    let mut unread_dates = Vec::new();
+    let created_at = read_date("Created", note.created.as_deref(), &mut unread_dates);
+    let updated_at = read_date("Updated", note.updated.as_deref(), &mut unread_dates);
+
+    let mut content = enml_to_markdown(&note.content, &note.attachments)?;
+    if !unread_dates.is_empty() {
+        content.push_str("\n\n");
+        content.push_str(&unread_dates.join("\n"));
+    }
+    if !note.attachments.is_empty() {
+        content.push_str("\n\n**Attachments (not imported):**\n");
+        for attachment in &note.attachments {
+            content.push_str(&format!("- {}\n", attachment.label()));
+        }
+    }
+    Ok(NoteDraft {
+        title: note.title.trim().to_string(),
+        content: content.trim().to_string(),
+        tags: note.tags,
+        created_at,
+        updated_at,
+    })
+}
+
+/// Parses an optional date. One that cannot be read is added to `unread` as a
+/// `Key: value` line for the content, rather than failing the whole file.
+fn read_date(key: &str, value: Option<&str>, unread: &mut Vec<String>) -> Option<u64> {
+    let value = value?;
+    match parse_enex_date(value) {
+        Ok(nanos) => Some(nanos),
+        Err(_) => {
+            unread.push(format!("{}: {}", key, value.trim()));
+            None
+        }
+    }
+}
+
+/// ENEX dates use the compact ISO 8601 form `20240131T093000Z`.
+fn parse_enex_date(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let expanded = match (value.get(0..4), value.get(4..6), value.get(6..8), value.get(8..9)) {
+        (Some(y), Some(m), Some(d), Some("T")) => {
+            let clock = &value[9..];
+            match (clock.get(0..2), clock.get(2..4), clock.get(4..6)) {
+                (Some(h), Some(min), Some(s)) => {
+                    format!("{}-{}-{}T{}:{}:{}{}", y, m, d, h, min, s, &clock[6..])
+                }
+                _ => value.to_string(),
+            }
+        }
+        _ => value.to_string(),
+    };
+    parse_rfc3339(&expanded).ok_or_else(|| format!("Invalid ENEX date: {}", value))
+}
+
+fn element_name(start: &BytesStart) -> String {
+    String::from_utf8_lossy(start.local_name().as_ref()).to_ascii_lowercase()
+}
+
+fn attribute(start: &BytesStart, key: &str) -> Option<String> {
+    start
+        .try_get_attribute(key)
+        .ok()
+        .flatten()
+        .and_then(|attr| attr.unescape_value_with(resolve_html5_entity).ok())
+        .map(|value| value.into_owned())
+}
+
+// --- ENML to Markdown ---
+
+enum ListKind {
+    Unordered,
+    Ordered(u32),
+}
+
+#[derive(Default)]
+struct MarkdownWriter {
+    out: String,
+    lists: Vec<ListKind>,
+    links: Vec<Option<String>>,
+    pre_depth: usize,
+    quote_depth: usize,
+    media_seen: usize,
+    cell_in_row: usize,
+}
+
+impl MarkdownWriter {
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn break_line(&mut self) {
+        if !self.at_line_start() {
+            self.out.push('\n');
+        }
+    }
+
+    fn start_block(&mut self) {
+        self.break_line();
+        for _ in 0..self.quote_depth {
+            self.out.push_str("> ");
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.pre_depth > 0 {
+            self.out.push_str(text);
+            return;
+        }
+        // Collapse HTML whitespace runs the way a browser would; &nbsp; survives.
+        for ch in text.chars() {
+            if ch.is_ascii_whitespace() {
+                if !self.at_line_start() && !self.out.ends_with(' ') {
+                    self.out.push(' ');
+                }
+            } else {
+                self.out.push(ch);
+            }
+        }
+    }
+
+    fn start(&mut self, start: &BytesStart, attachments: &[Attachment], empty: bool) {
+        let name = element_name(start);
+        match name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.start_block();
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+            }
+            "div" | "p" => self.start_block(),
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.break_line();
+                self.out.push_str("---\n");
+            }
+            "b" | "strong" => self.out.push_str("**"),
+            "i" | "em" => self.out.push('_'),
+            "s" | "strike" | "del" => self.out.push_str("~~"),
+            "code" if self.pre_depth == 0 => self.out.push('`'),
+            "pre" => {
+                self.break_line();
+                self.out.push_str("```\n");
+                self.pre_depth += 1;
+            }
+            "blockquote" => {
+                self.quote_depth += 1;
+                self.start_block();
+            }
+            "ul" => self.lists.push(ListKind::Unordered),
+            "ol" => self.lists.push(ListKind::Ordered(0)),
+            "li" => {
+                self.break_line();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                self.out.push_str(&indent);
+                match self.lists.last_mut() {
+                    Some(ListKind::Ordered(n)) => {
+                        *n += 1;
+                        let marker = format!("{}. ", n);
+                        self.out.push_str(&marker);
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            "a" => {
+                self.links.push(attribute(start, "href"));
+                self.out.push('[');
+            }
+            "img" => {
+                let alt = attribute(start, "alt").unwrap_or_default();
+                let src = attribute(start, "src").unwrap_or_default();
+                self.out.push_str(&format!("![{}]({})", alt, src));
+            }
+            "en-todo" => {
+                let checked = attribute(start, "checked").as_deref() == Some("true");
+                if self.at_line_start() {
+                    self.out.push_str("- ");
+                }
+                self.out.push_str(if checked { "[x] " } else { "[ ] " });
+            }
+            "en-media" => {
+                // en-media references resources by MD5 hash; resources appear in
+                // the same order as their embeds in notes Evernote writes.
+                let label = attachments
+                    .get(self.media_seen)
+                    .map(Attachment::label)
+                    .or_else(|| attribute(start, "type"))
+                    .unwrap_or_else(|| "attachment".to_string());
+                self.media_seen += 1;
+                self.out.push_str(&format!("[Attachment: {}]", label));
+            }
+            "tr" => {
+                self.break_line();
+                self.cell_in_row = 0;
+            }
+            "td" | "th" => {
+                if self.cell_in_row > 0 {
+                    self.out.push_str(" | ");
+                }
+                self.cell_in_row += 1;
+            }
+            _ => {}
+        }
+        if empty {
+            self.end(&name);
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "div" | "p" | "li" | "tr" => self.break_line(),
+            "b" | "strong" => self.out.push_str("**"),
+            "i" | "em" => self.out.push('_'),
+            "s" | "strike" | "del" => self.out.push_str("~~"),
+            "code" if self.pre_depth == 0 => self.out.push('`'),
+            "pre" => {
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                self.break_line();
+                self.out.push_str("```\n");
+            }
+            "blockquote" => {
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.break_line();
+            }
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.break_line();
+            }
+            "a" => {
+                let href = self.links.pop().flatten().unwrap_or_default();
+                self.out.push_str(&format!("]({})", href));
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        let mut blank_run = 0;
+        for line in self.out.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                blank_run += 1;
+                if blank_run > 1 {
+                    continue;
+                }
+            } else {
+                blank_run = 0;
+            }
+            lines.push(line);
+        }
+        lines.join("\n").trim().to_string()
+    }
+}
+
+/// Converts an ENML document to Markdown.
+fn enml_to_markdown(enml: &str, attachments: &[Attachment]) -> Result<String, String> {
+    let mut reader = Reader::from_str(enml);
+    reader.config_mut().check_end_names = false;
+    let mut writer = MarkdownWriter::default();
+
+    loop {
+        let event = reader.read_event().map_err(|e| format!("Invalid ENML: {}", e))?;
+        match event {
+            Event::Start(start) => writer.start(&start, attachments, false),
+            Event::Empty(start) => writer.start(&start, attachments, true),
+            Event::End(end) => {
+                let name = String::from_utf8_lossy(end.local_name().as_ref()).to_ascii_lowercase();
+                writer.end(&name);
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape_with(resolve_html5_entity)
+                    .map_err(|e| format!("Invalid ENML text: {}", e))?;
+                writer.push_text(&text);
+            }
+            Event::CData(data) => writer.push_text(&String::from_utf8_lossy(&data)),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(writer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-export SYSTEM "http://xml.evernote.com/pub/evernote-export4.dtd">
+<en-export export-date="20240201T080000Z" application="Evernote" version="10">
+  <note>
+    <title>Trip &amp; plans</title>
+    <content><![CDATA[<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd">
+<en-note><div><b>Pack</b>&nbsp;list:</div><ul><li>Passport</li><li><a href="https://example.com">Tickets</a></li></ul><div><en-todo checked="true"/>Book hotel</div><div><br/></div><en-media hash="abc" type="image/png"/></en-note>]]></content>
+    <created>20240131T093000Z</created>
+    <updated>20240201T101500Z</updated>
+    <tag>travel</tag>
+    <tag>personal</tag>
+    <resource>
+      <data encoding="base64">iVBORw0KGgo=</data>
+      <mime>image/png</mime>
+      <resource-attributes><file-name>map.png</file-name></resource-attributes>
+    </resource>
+  </note>
+</en-export>"#;
+
+    #[test]
+    fn test_parse_enex_note() {
+        let drafts = parse_enex(SAMPLE).unwrap();
+        assert_eq!(drafts.len(), 1);
+        let draft = &drafts[0];
+        assert_eq!(draft.title, "Trip & plans");
+        assert_eq!(draft.tags, vec!["travel".to_string(), "personal".to_string()]);
+        assert_eq!(draft.created_at, parse_rfc3339("2024-01-31T09:30:00Z"));
+        assert_eq!(draft.updated_at, parse_rfc3339("2024-02-01T10:15:00Z"));
+        assert_eq!(
+            draft.content,
+            "**Pack**\u{a0}list:\n- Passport\n- [Tickets](https://example.com)\n- [x] Book hotel\n\n\
+             [Attachment: map.png]\n\n**Attachments (not imported):**\n- map.png"
+        );
+    }
+
+    #[test]
+    fn test_enml_headings_and_code() {
+        let markdown = enml_to_markdown(
+            "<en-note><h2>Steps</h2><ol><li>One</li><li>Two</li></ol><pre>let x = 1;\nlet y = 2;</pre></en-note>",
+            &[],
+        )
+        .unwrap();
+        assert_eq!(markdown, "## Steps\n1. One\n2. Two\n```\nlet x = 1;\nlet y = 2;\n```");
+    }
+
+    #[test]
+    fn test_invalid_enex_date_is_kept_as_text() {
+        let xml = "<en-export>\
+            <note><title>T</title><content>x</content><created>yesterday</created></note>\
+            <note><title>U</title><content>y</content><created>20240131T093000Z</created></note>\
+            </en-export>";
+        let drafts = parse_enex(xml).unwrap();
+        assert_eq!((drafts[0].created_at, drafts[0].content.as_str()), (None, "x\n\nCreated: yesterday"));
+        assert_eq!(drafts[1].created_at, parse_rfc3339("2024-01-31T09:30:00Z"));
+        assert_eq!(parse_enex_date("yesterday").unwrap_err(), "Invalid ENEX date: yesterday");
+    }
+}
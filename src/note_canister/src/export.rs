@@ -0,0 +1,342 @@
+use candid::{CandidType, Deserialize, Principal};
+
+use std::collections::BTreeSet;
+
+use crate::checklist::Checklist;
+use crate::csv;
+use crate::datetime::format_rfc3339;
+use crate::markdown::{render_document, slugify, FrontMatterValue};
+use crate::properties::PropertyValue;
+use crate::{Note, NoteStore};
+
+// --- Constants ---
+pub const DEFAULT_EXPORT_PAGE_SIZE: u32 = 50;
+pub const MAX_EXPORT_PAGE_SIZE: u32 = 200;
+/// Rendered bytes per chunk, well below the 3 MiB limit on query responses.
+const MAX_EXPORT_CHUNK_BYTES: usize = 2 * 1024 * 1024;
+
+// --- Data Structures ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum ExportFormat {
+    /// One JSON array per chunk with every stored field of each note.
+    Json,
+    /// One Markdown file per note with YAML front matter. Properties become
+    /// `properties.<key>` fields and checklist items a task list after the content.
+    Markdown,
+    /// One CSV file per chunk, header row included, with a `checklist` column
+    /// and a `properties.<key>` column for each property in the chunk.
+    Csv,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct ExportFile {
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ExportChunk {
+    pub format: ExportFormat,
+    pub files: Vec<ExportFile>,
+    /// Pass back as `cursor` to fetch the next chunk; `None` once exhausted.
+    pub next_cursor: Option<u64>,
+    pub total_notes: u64,
+}
+
+/// Builds one chunk of `owner`'s notes, ordered by id and starting after `cursor`.
+/// A chunk ends early once its notes would render to more than
+/// `MAX_EXPORT_CHUNK_BYTES`, but always holds at least one note.
+/// Encrypted notes are skipped; only clients holding their keys can render them.
+pub fn export_chunk(
+    notes: &NoteStore,
+    owner: Principal,
+    format: ExportFormat,
+    cursor: Option<u64>,
+    limit: Option<u32>,
+) -> Result<ExportChunk, String> {
+    let limit = limit
+        .unwrap_or(DEFAULT_EXPORT_PAGE_SIZE)
+        .clamp(1, MAX_EXPORT_PAGE_SIZE) as usize;
+
+    let mut owned: Vec<&Note> = notes
+        .values()
+        .filter(|note| note.owner == owner && note.encrypted.is_none())
+        .collect();
+    owned.sort_by_key(|note| note.id);
+    let total_notes = owned.len() as u64;
+
+    let remaining: Vec<&Note> = owned
+        .into_iter()
+        .filter(|note| cursor.is_none_or(|after| note.id > after))
+        .collect();
+    let mut hydrated: Vec<Note> = Vec::new();
+    let mut rendered_bytes = 0;
+    for note in remaining.iter().take(limit) {
+        let note = crate::hydrate(note);
+        let bytes = rendered_len(&note, format);
+        if !hydrated.is_empty() && rendered_bytes + bytes > MAX_EXPORT_CHUNK_BYTES {
+            break;
+        }
+        rendered_bytes += bytes;
+        hydrated.push(note);
+    }
+    let page_refs: Vec<&Note> = hydrated.iter().collect();
+    let page = &page_refs[..];
+    let next_cursor = if remaining.len() > page.len() {
+        page.last().map(|note| note.id)
+    } else {
+        None
+    };
+
+    let files = match (page.first(), page.last()) {
+        (Some(first), Some(last)) => match format {
+            ExportFormat::Json => vec![ExportFile {
+                name: format!("notes-{}-{}.json", first.id, last.id),
+                content: serde_json::to_string_pretty(page)
+                    .map_err(|e| format!("Failed to serialize notes: {}", e))?,
+            }],
+            ExportFormat::Markdown => page.iter().map(|note| note_to_markdown(note)).collect(),
+            ExportFormat::Csv => vec![ExportFile {
+                name: format!("notes-{}-{}.csv", first.id, last.id),
+                content: notes_to_csv(page),
+            }],
+        },
+        _ => Vec::new(),
+    };
+
+    Ok(ExportChunk {
+        format,
+        files,
+        next_cursor,
+        total_notes,
+    })
+}
+
+/// Bytes `note` adds to a chunk in `format`.
+fn rendered_len(note: &Note, format: ExportFormat) -> usize {
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(note).map_or(0, |json| json.len()),
+        ExportFormat::Markdown => note_to_markdown(note).content.len(),
+        ExportFormat::Csv => notes_to_csv(&[note]).len(),
+    }
+}
+
+fn note_to_markdown(note: &Note) -> ExportFile {
+    let mut fields = vec![
+        ("id", FrontMatterValue::Text(note.id.to_string())),
+        ("title", FrontMatterValue::Text(note.title.clone())),
+        ("owner", FrontMatterValue::Text(note.owner.to_text())),
+        ("created_at", FrontMatterValue::Text(format_rfc3339(note.created_at))),
+    ];
+    if let Some(updated_at) = note.updated_at {
+        fields.push(("updated_at", FrontMatterValue::Text(format_rfc3339(updated_at))));
+    }
+    fields.push(("tags", FrontMatterValue::List(note.tags.clone())));
+    let property_keys: Vec<String> = note.properties.iter().flatten().map(|(key, _)| property_column(key)).collect();
+    for ((_, value), key) in note.properties.iter().flatten().zip(&property_keys) {
+        fields.push((key.as_str(), FrontMatterValue::Text(property_text(value))));
+    }
+
+    let mut body = note.content.clone();
+    if let Some(checklist) = note.checklist.as_ref().filter(|checklist| !checklist.items.is_empty()) {
+        if !body.is_empty() {
+            body.push_str(if body.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        body.push_str(&task_list(checklist));
+    }
+    ExportFile {
+        name: format!("{}-{}.md", note.id, slugify(&note.title)),
+        content: render_document(&fields, &body),
+    }
+}
+
+/// Checklist items as a Markdown task list, one `- [ ]` or `- [x]` line each.
+fn task_list(checklist: &Checklist) -> String {
+    checklist
+        .items
+        .iter()
+        .map(|item| format!("- [{}] {}\n", if item.done { 'x' } else { ' ' }, item.text.replace('\n', " ")))
+        .collect()
+}
+
+fn property_column(key: &str) -> String {
+    format!("properties.{}", key)
+}
+
+fn property_text(value: &PropertyValue) -> String {
+    match value {
+        PropertyValue::Text(text) => text.clone(),
+        PropertyValue::Number(number) => number.to_string(),
+        PropertyValue::Date(nanos) => format_rfc3339(*nanos),
+        PropertyValue::Bool(flag) => flag.to_string(),
+        PropertyValue::Principal(principal) => principal.to_text(),
+    }
+}
+
+const CSV_HEADER: [&str; 8] = ["id", "owner", "title", "content", "created_at", "updated_at", "tags", "checklist"];
+
+/// Property columns follow the fixed ones, sorted by key; notes without a
+/// property leave its cell empty.
+fn notes_to_csv(notes: &[&Note]) -> String {
+    let property_keys: BTreeSet<&String> =
+        notes.iter().flat_map(|note| note.properties.iter().flatten().map(|(key, _)| key)).collect();
+    let header = CSV_HEADER.iter().map(|h| h.to_string());
+    let mut out = csv::write_row(header.chain(property_keys.iter().map(|key| property_column(key))));
+    for note in notes {
+        let fixed = [
+            note.id.to_string(),
+            note.owner.to_text(),
+            note.title.clone(),
+            note.content.clone(),
+            format_rfc3339(note.created_at),
+            note.updated_at.map(format_rfc3339).unwrap_or_default(),
+            note.tags.join(";"),
+            note.checklist.as_ref().map(task_list).unwrap_or_default(),
+        ];
+        let properties = property_keys.iter().map(|key| {
+            note.properties
+                .as_ref()
+                .and_then(|properties| properties.get(*key))
+                .map(property_text)
+                .unwrap_or_default()
+        });
+        out.push_str(&csv::write_row(fixed.into_iter().chain(properties)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with(owner: Principal, count: u64) -> NoteStore {
+        (1..=count)
+            .map(|id| {
+                (
+                    id,
+                    Note {
+                        id,
+                        owner,
+                        title: format!("Note {}", id),
+                        content: "Hello, \"world\"".to_string(),
+                        created_at: id * 1_000_000_000,
+                        tags: vec!["work".to_string(), "q1".to_string()],
+                        updated_at: None,
+                        expires_at: None,
+                        encrypted: None,
+                        content_hash: None,
+                        remind_at: None,
+                        checklist: None,
+                        properties: None,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_export_paginates_by_cursor() {
+        let owner = Principal::from_slice(&[1; 29]);
+        let mut notes = store_with(owner, 5);
+        notes.insert(
+            99,
+            Note {
+                id: 99,
+                owner: Principal::anonymous(),
+                title: "Other".to_string(),
+                content: "Not mine".to_string(),
+                created_at: 0,
+                tags: Vec::new(),
+                updated_at: None,
+                expires_at: None,
+                encrypted: None,
+                content_hash: None,
+                remind_at: None,
+                checklist: None,
+                properties: None,
+            },
+        );
+
+        let first = export_chunk(&notes, owner, ExportFormat::Markdown, None, Some(2)).unwrap();
+        assert_eq!(first.total_notes, 5);
+        assert_eq!(first.files.len(), 2);
+        assert_eq!(first.files[0].name, "1-note-1.md");
+        assert_eq!(first.next_cursor, Some(2));
+
+        let last = export_chunk(&notes, owner, ExportFormat::Markdown, Some(4), Some(2)).unwrap();
+        assert_eq!(last.files.len(), 1);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn test_export_csv_and_json() {
+        let owner = Principal::from_slice(&[1; 29]);
+        let notes = store_with(owner, 1);
+
+        let csv = export_chunk(&notes, owner, ExportFormat::Csv, None, None).unwrap();
+        let lines: Vec<&str> = csv.files[0].content.split("\r\n").collect();
+        assert_eq!(lines[0], "id,owner,title,content,created_at,updated_at,tags,checklist");
+        assert!(lines[1].ends_with(",Note 1,\"Hello, \"\"world\"\"\",1970-01-01T00:00:01Z,,work;q1,"));
+
+        let json = export_chunk(&notes, owner, ExportFormat::Json, None, None).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json.files[0].content).unwrap();
+        assert_eq!(parsed[0]["owner"], owner.to_text());
+        assert_eq!(parsed[0]["created_at"], 1_000_000_000u64);
+        assert_eq!(parsed[0]["tags"][1], "q1");
+    }
+
+    #[test]
+    fn test_export_chunks_stop_at_byte_budget() {
+        let owner = Principal::from_slice(&[1; 29]);
+        let mut notes = store_with(owner, 3);
+        for note in notes.values_mut() {
+            note.content = "x".repeat(MAX_EXPORT_CHUNK_BYTES * 2 / 5);
+        }
+
+        for format in [ExportFormat::Json, ExportFormat::Markdown, ExportFormat::Csv] {
+            let first = export_chunk(&notes, owner, format, None, None).unwrap();
+            assert_eq!(first.next_cursor, Some(2));
+            let rest = export_chunk(&notes, owner, format, first.next_cursor, None).unwrap();
+            assert_eq!(rest.next_cursor, None);
+        }
+
+        // A single note over the budget is still exported on its own.
+        notes.get_mut(&1).unwrap().content = "x".repeat(MAX_EXPORT_CHUNK_BYTES + 1);
+        let single = export_chunk(&notes, owner, ExportFormat::Markdown, None, None).unwrap();
+        assert_eq!((single.files.len(), single.next_cursor), (1, Some(1)));
+    }
+
+    #[test]
+    fn test_export_checklists_and_properties() {
+        let owner = Principal::from_slice(&[1; 29]);
+        let mut notes = store_with(owner, 2);
+        let note = notes.get_mut(&1).unwrap();
+        let mut checklist = Checklist::default();
+        for text in ["Milk", "Eggs"] {
+            checklist
+                .add_item(crate::checklist::NewChecklistItem {
+                    text: text.to_string(),
+                    due_at: None,
+                    assignee: None,
+                })
+                .unwrap();
+        }
+        checklist.items[1].done = true;
+        note.checklist = Some(checklist);
+        note.properties = Some(crate::properties::Properties::from([
+            ("status".to_string(), PropertyValue::Text("open".to_string())),
+            ("priority".to_string(), PropertyValue::Number(2.0)),
+        ]));
+
+        let markdown = export_chunk(&notes, owner, ExportFormat::Markdown, None, Some(1)).unwrap();
+        let content = &markdown.files[0].content;
+        assert!(content.contains("properties.priority: \"2\"\nproperties.status: \"open\"\n"));
+        assert!(content.ends_with("Hello, \"world\"\n\n- [ ] Milk\n- [x] Eggs\n"));
+
+        let csv = export_chunk(&notes, owner, ExportFormat::Csv, None, None).unwrap();
+        let rows = csv::parse(&csv.files[0].content).unwrap();
+        assert_eq!(rows[0][7..], ["checklist", "properties.priority", "properties.status"]);
+        assert_eq!(rows[1][7..], ["- [ ] Milk\n- [x] Eggs\n", "2", "open"]);
+        assert_eq!(rows[2][7..], ["", "", ""]);
+    }
+}
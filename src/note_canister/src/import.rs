@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::datetime::parse_rfc3339;
+use crate::enex::parse_enex;
+use crate::markdown::{parse_document, Document, FrontMatterValue};
+use crate::notion::parse_notion_export;
+
+// --- Constants ---
+pub const MAX_IMPORT_BATCH: usize = 100;
+pub const MAX_IMPORT_JOBS_PER_OWNER: usize = 3;
+pub const MAX_IMPORT_UPLOAD_BYTES: usize = 8 * 1024 * 1024;
+/// Heap held by all open import jobs together, across every owner.
+pub const MAX_OPEN_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+const MAX_EXPANDED_ARCHIVE_BYTES: usize = 32 * 1024 * 1024;
+/// Jobs untouched for this long are dropped by the expiry sweeper.
+const IMPORT_JOB_IDLE_NANOS: u64 = 30 * 60 * 1_000_000_000;
+
+// --- Data Structures ---
+/// One note to import, in the same shapes `export_notes` produces.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum ImportItem {
+    /// A single JSON object with `title`, `content` and optional `tags`/`created_at`.
+    Json(String),
+    /// A Markdown document with `title`, `tags` and `created_at` in its front matter.
+    Markdown(String),
+}
+
+/// A parsed but not yet validated note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoteDraft {
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    /// Original creation time in nanoseconds, when the source carried one.
+    pub created_at: Option<u64>,
+    pub updated_at: Option<u64>,
+}
+
+impl NoteDraft {
+    pub fn new(title: String, content: String) -> Self {
+        NoteDraft {
+            title,
+            content,
+            tags: Vec::new(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+/// Third-party export formats that are uploaded in chunks and imported in batches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum ImportSource {
+    /// An Evernote `.enex` file.
+    Enex,
+    /// A Notion "Markdown & CSV" export `.zip`.
+    NotionZip,
+}
+
+/// An upload in progress or a parsed archive waiting to be imported.
+/// Jobs live in heap memory only and are dropped on upgrade, or once idle for
+/// `IMPORT_JOB_IDLE_NANOS`.
+pub struct ImportJob {
+    pub owner: Principal,
+    pub source: ImportSource,
+    pub upload: Vec<u8>,
+    /// Filled from `upload` on the first batch run.
+    pub pending: Option<VecDeque<NoteDraft>>,
+    /// Last upload or batch run, or when the job was opened.
+    pub touched_at: u64,
+}
+
+impl ImportJob {
+    pub fn new(owner: Principal, source: ImportSource, now: u64) -> Self {
+        ImportJob {
+            owner,
+            source,
+            upload: Vec::new(),
+            pending: None,
+            touched_at: now,
+        }
+    }
+
+    pub fn is_idle(&self, now: u64) -> bool {
+        now.saturating_sub(self.touched_at) >= IMPORT_JOB_IDLE_NANOS
+    }
+
+    /// Heap held by the job: its upload, or the drafts parsed from it.
+    pub fn held_bytes(&self) -> usize {
+        self.upload.len() + self.pending.iter().flatten().map(draft_bytes).sum::<usize>()
+    }
+
+    /// Checks that `chunk_len` more bytes fit both this job's limit and the
+    /// limit on `open_bytes`, the bytes held by all open jobs.
+    pub fn check_upload(&self, open_bytes: usize, chunk_len: usize) -> Result<(), String> {
+        if self.upload.len() + chunk_len > MAX_IMPORT_UPLOAD_BYTES {
+            return Err(format!("Import uploads are limited to {} bytes.", MAX_IMPORT_UPLOAD_BYTES));
+        }
+        if open_bytes + chunk_len > MAX_OPEN_IMPORT_BYTES {
+            return Err("Too many imports are in progress. Try again later.".to_string());
+        }
+        Ok(())
+    }
+
+    /// Parses the uploaded bytes, leaving the job unchanged.
+    pub fn parse_upload(&self) -> Result<Vec<NoteDraft>, String> {
+        match self.source {
+            ImportSource::Enex => {
+                let xml = std::str::from_utf8(&self.upload).map_err(|_| "ENEX file is not valid UTF-8.".to_string())?;
+                parse_enex(xml)
+            }
+            ImportSource::NotionZip => parse_notion_export(&self.upload, MAX_EXPANDED_ARCHIVE_BYTES),
+        }
+    }
+
+    /// Replaces the upload with the drafts parsed from it, unless they would
+    /// push `other_open_bytes`, the bytes held by every other open job, past
+    /// the limit. Expanding an archive can make the drafts several times
+    /// larger than the upload the limit was checked against.
+    pub fn queue_drafts(&mut self, drafts: Vec<NoteDraft>, other_open_bytes: usize) -> Result<(), String> {
+        if other_open_bytes + drafts.iter().map(draft_bytes).sum::<usize>() > MAX_OPEN_IMPORT_BYTES {
+            return Err("Too many imports are in progress. Try again later.".to_string());
+        }
+        self.upload = Vec::new();
+        self.pending = Some(drafts.into());
+        Ok(())
+    }
+}
+
+fn draft_bytes(draft: &NoteDraft) -> usize {
+    draft.title.len() + draft.content.len() + draft.tags.iter().map(String::len).sum::<usize>()
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ImportItemReport {
+    pub title: String,
+    pub result: Result<u64, String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ImportBatchReport {
+    pub results: Vec<ImportItemReport>,
+    /// Notes still queued; the job is finished and removed once this is zero.
+    pub remaining: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonNoteRecord {
+    title: String,
+    content: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    created_at: Option<JsonTimestamp>,
+    #[serde(default)]
+    updated_at: Option<JsonTimestamp>,
+}
+
+/// `created_at` as exported (nanoseconds) or as an RFC 3339 string.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonTimestamp {
+    Nanos(u64),
+    Text(String),
+}
+
+pub fn parse_item(item: &ImportItem) -> Result<NoteDraft, String> {
+    match item {
+        ImportItem::Json(text) => parse_json(text),
+        ImportItem::Markdown(text) => parse_markdown(text),
+    }
+}
+
+fn parse_json(text: &str) -> Result<NoteDraft, String> {
+    let record: JsonNoteRecord =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON note: {}", e))?;
+    Ok(NoteDraft {
+        title: record.title,
+        content: record.content,
+        tags: record.tags,
+        created_at: record.created_at.map(JsonTimestamp::into_nanos).transpose()?,
+        updated_at: record.updated_at.map(JsonTimestamp::into_nanos).transpose()?,
+    })
+}
+
+impl JsonTimestamp {
+    fn into_nanos(self) -> Result<u64, String> {
+        match self {
+            JsonTimestamp::Nanos(nanos) => Ok(nanos),
+            JsonTimestamp::Text(text) => parse_timestamp(&text),
+        }
+    }
+}
+
+fn parse_markdown(text: &str) -> Result<NoteDraft, String> {
+    let mut doc = parse_document(text)?;
+    let title = match doc.take("title") {
+        Some(FrontMatterValue::Text(title)) => title,
+        Some(FrontMatterValue::List(_)) => return Err("Front matter 'title' must be a string.".to_string()),
+        None => return Err("Front matter is missing a 'title' field.".to_string()),
+    };
+    let tags = doc.take("tags").map(FrontMatterValue::into_list).unwrap_or_default();
+    let created_at = take_timestamp(&mut doc, "created_at")?;
+    let updated_at = take_timestamp(&mut doc, "updated_at")?;
+    Ok(NoteDraft {
+        title,
+        content: doc.body,
+        tags,
+        created_at,
+        updated_at,
+    })
+}
+
+fn take_timestamp(doc: &mut Document, key: &str) -> Result<Option<u64>, String> {
+    match doc.take(key) {
+        Some(FrontMatterValue::Text(text)) => Ok(Some(parse_timestamp(&text)?)),
+        Some(FrontMatterValue::List(_)) => Err(format!("Front matter '{}' must be a timestamp.", key)),
+        None => Ok(None),
+    }
+}
+
+/// Accepts RFC 3339 text or a raw nanosecond count.
+pub fn parse_timestamp(text: &str) -> Result<u64, String> {
+    text.trim()
+        .parse::<u64>()
+        .ok()
+        .or_else(|| parse_rfc3339(text))
+        .ok_or_else(|| format!("Invalid timestamp: {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_limits() {
+        let mut job = ImportJob::new(Principal::anonymous(), ImportSource::Enex, 0);
+        assert!(job.check_upload(0, MAX_IMPORT_UPLOAD_BYTES).is_ok());
+        assert!(job.check_upload(MAX_OPEN_IMPORT_BYTES - 10, 11).is_err());
+
+        job.upload = vec![0; 10];
+        assert!(job.check_upload(10, MAX_IMPORT_UPLOAD_BYTES - 9).is_err());
+        job.pending = Some(VecDeque::from([NoteDraft::new("Title".to_string(), "Body".to_string())]));
+        assert_eq!(job.held_bytes(), 19);
+
+        let drafts = vec![NoteDraft::new("T".to_string(), "x".repeat(100))];
+        assert!(job.queue_drafts(drafts.clone(), MAX_OPEN_IMPORT_BYTES - 100).is_err());
+        assert_eq!(job.upload.len(), 10);
+        job.queue_drafts(drafts, MAX_OPEN_IMPORT_BYTES - 101).unwrap();
+        assert_eq!((job.upload.len(), job.held_bytes()), (0, 101));
+
+        assert!(!job.is_idle(IMPORT_JOB_IDLE_NANOS - 1));
+        assert!(job.is_idle(IMPORT_JOB_IDLE_NANOS));
+    }
+
+    #[test]
+    fn test_parse_json_item() {
+        let draft = parse_item(&ImportItem::Json(
+            r#"{"id": 7, "title": "T", "content": "C", "tags": ["x"], "created_at": "2023-11-14T22:13:20Z"}"#
+                .to_string(),
+        ))
+        .unwrap();
+        assert_eq!(draft.title, "T");
+        assert_eq!(draft.tags, vec!["x".to_string()]);
+        assert_eq!(draft.created_at, Some(1_700_000_000_000_000_000));
+
+        let nanos = parse_item(&ImportItem::Json(r#"{"title": "T", "content": "C", "created_at": 42}"#.to_string()));
+        assert_eq!(nanos.unwrap().created_at, Some(42));
+
+        assert!(parse_item(&ImportItem::Json(r#"{"title": "T"}"#.to_string())).is_err());
+    }
+
+    #[test]
+    fn test_parse_markdown_item() {
+        let draft = parse_item(&ImportItem::Markdown(
+            "---\ntitle: Groceries\ntags: [home, errands]\ncreated_at: 2024-01-01\n---\n\n- milk\n".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(
+            draft,
+            NoteDraft {
+                title: "Groceries".to_string(),
+                content: "- milk\n".to_string(),
+                tags: vec!["home".to_string(), "errands".to_string()],
+                created_at: parse_rfc3339("2024-01-01"),
+                updated_at: None,
+            }
+        );
+
+        let missing_title = parse_item(&ImportItem::Markdown("---\ntags: [a]\n---\nBody".to_string()));
+        assert_eq!(missing_title.unwrap_err(), "Front matter is missing a 'title' field.");
+    }
+}
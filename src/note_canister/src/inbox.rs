@@ -0,0 +1,145 @@
+// --- Notification Inbox ---
+// Every principal has its own list of notifications, oldest first. Other
+// features push into it; ids are unique across all inboxes. Each inbox keeps
+// only its newest `MAX_INBOX_SIZE` notifications.
+
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+pub const MAX_INBOX_SIZE: usize = 200;
+const DEFAULT_PAGE: usize = 50;
+const MAX_PAGE: usize = 100;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub enum NotificationKind {
+    /// A reminder set with `set_reminder` came due.
+    ReminderDue { note_id: u64, due_at: u64 },
+    /// `by` gave the recipient a key to one of their encrypted notes.
+    SharedWithYou { note_id: u64, by: Principal },
+    /// `by` commented on the recipient's note or replied to their comment.
+    Comment { note_id: u64, comment_id: u64, by: Principal },
+    /// `by` mentioned the recipient in a note's content.
+    Mention { note_id: u64, by: Principal },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Notification {
+    pub id: u64,
+    pub kind: NotificationKind,
+    pub created_at: u64,
+    pub read: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct NotificationPage {
+    pub notifications: Vec<Notification>,
+    /// Pass as `cursor` to fetch older notifications; `None` once the start is reached.
+    pub next_cursor: Option<u64>,
+    pub unread_count: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Inboxes {
+    last_id: u64,
+    inboxes: HashMap<Principal, Vec<Notification>>,
+}
+
+impl Inboxes {
+    /// Adds an unread notification to `recipient`'s inbox, dropping the oldest
+    /// one if the inbox is full, and returns its id.
+    pub fn push(&mut self, recipient: Principal, now: u64, kind: NotificationKind) -> u64 {
+        self.last_id += 1;
+        let inbox = self.inboxes.entry(recipient).or_default();
+        if inbox.len() >= MAX_INBOX_SIZE {
+            inbox.drain(..=inbox.len() - MAX_INBOX_SIZE);
+        }
+        inbox.push(Notification {
+            id: self.last_id,
+            kind,
+            created_at: now,
+            read: false,
+        });
+        self.last_id
+    }
+
+    pub fn get(&self, recipient: Principal) -> &[Notification] {
+        self.inboxes.get(&recipient).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn unread_count(&self, recipient: Principal) -> u64 {
+        self.get(recipient).iter().filter(|notification| !notification.read).count() as u64
+    }
+
+    /// Newest-first page of `recipient`'s notifications. `cursor` is the id to start below.
+    pub fn page(&self, recipient: Principal, cursor: Option<u64>, limit: Option<u32>, unread_only: bool) -> NotificationPage {
+        let limit = limit.map_or(DEFAULT_PAGE, |l| (l as usize).clamp(1, MAX_PAGE));
+        let inbox = self.get(recipient);
+        let end = cursor.map_or(inbox.len(), |cursor| inbox.partition_point(|notification| notification.id < cursor));
+        let mut matching = inbox[..end]
+            .iter()
+            .rev()
+            .filter(|notification| !unread_only || !notification.read);
+        let notifications: Vec<Notification> = matching.by_ref().take(limit).cloned().collect();
+        let next_cursor = match notifications.last() {
+            Some(last) if matching.next().is_some() => Some(last.id),
+            _ => None,
+        };
+        NotificationPage {
+            notifications,
+            next_cursor,
+            unread_count: self.unread_count(recipient),
+        }
+    }
+
+    /// Marks the listed notifications (or all of them, for `None`) as read and
+    /// returns how many were unread. Unknown ids are ignored.
+    pub fn mark_read(&mut self, recipient: Principal, ids: Option<&[u64]>) -> u64 {
+        let Some(inbox) = self.inboxes.get_mut(&recipient) else {
+            return 0;
+        };
+        let mut marked = 0;
+        for notification in inbox.iter_mut() {
+            if !notification.read && ids.is_none_or(|ids| ids.contains(&notification.id)) {
+                notification.read = true;
+                marked += 1;
+            }
+        }
+        marked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inbox_is_bounded_and_paged_newest_first() {
+        let alice = Principal::from_slice(&[1; 29]);
+        let bob = Principal::from_slice(&[2; 29]);
+        let mut inboxes = Inboxes::default();
+        let reminder = |note_id| NotificationKind::ReminderDue { note_id, due_at: 0 };
+        for note_id in 0..MAX_INBOX_SIZE as u64 + 5 {
+            inboxes.push(alice, note_id, reminder(note_id));
+        }
+        inboxes.push(bob, 0, reminder(0));
+
+        let all = inboxes.get(alice);
+        assert_eq!(all.len(), MAX_INBOX_SIZE);
+        assert_eq!(all[0].kind, reminder(5));
+        let newest = all[MAX_INBOX_SIZE - 1].id;
+
+        assert_eq!(inboxes.mark_read(alice, Some(&[newest, 9999])), 1);
+        let first = inboxes.page(alice, None, Some(2), true);
+        assert_eq!(first.unread_count, MAX_INBOX_SIZE as u64 - 1);
+        assert_eq!(first.notifications[0].kind, reminder(MAX_INBOX_SIZE as u64 + 3));
+        let second = inboxes.page(alice, first.next_cursor, Some(2), true);
+        assert_eq!(second.notifications[0].kind, reminder(MAX_INBOX_SIZE as u64 + 1));
+
+        assert_eq!(inboxes.mark_read(alice, None), MAX_INBOX_SIZE as u64 - 1);
+        assert_eq!(inboxes.unread_count(alice), 0);
+        assert_eq!(inboxes.unread_count(bob), 1);
+    }
+}
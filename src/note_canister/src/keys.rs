@@ -0,0 +1,284 @@
+// --- Key Derivation ---
+// Keys for encrypted notes come from the IC's threshold vetKD API: the canister
+// asks the subnet to derive a key for an input (a principal or a note id) and
+// the subnet returns it encrypted under a transport key chosen by the client,
+// so neither the canister nor any single node learns it. `LocalKeyDeriver`
+// stands in where no vetKD key is available.
+
+use candid::{CandidType, Deserialize, Principal};
+use sha2::{Digest, Sha256};
+
+use crate::config::KeyDerivation;
+
+// --- Constants ---
+/// Separates per-user keys from per-note keys derived from the same master key.
+const USER_KEY_CONTEXT: &[u8] = b"note_canister/user-key/v1";
+const NOTE_KEY_CONTEXT: &[u8] = b"note_canister/note-key/v1";
+// Cycles attached to `vetkd_derive_key`, per the published management canister fees.
+const VETKD_TEST_KEY_FEE: u128 = 10_000_000_000;
+const VETKD_PRODUCTION_KEY_FEE: u128 = 26_153_846_153;
+const MAX_TRANSPORT_KEY_BYTES: usize = 256;
+
+// --- Data Structures ---
+/// Which family of keys a request is for; each has its own public key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum KeyScope {
+    /// Identity-based keys, one per principal, for wrapping note keys to a recipient.
+    User,
+    /// One key per encrypted note, for its owner and recipients.
+    Note,
+}
+
+impl KeyScope {
+    fn context(self) -> &'static [u8] {
+        match self {
+            KeyScope::User => USER_KEY_CONTEXT,
+            KeyScope::Note => NOTE_KEY_CONTEXT,
+        }
+    }
+}
+
+/// Source of derived keys. Derived keys are returned encrypted under the
+/// caller's transport public key.
+#[allow(async_fn_in_trait)]
+pub trait KeyDeriver {
+    async fn public_key(&self, context: &[u8]) -> Result<Vec<u8>, String>;
+    async fn derive_key(&self, context: &[u8], input: &[u8], transport_public_key: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+pub fn user_key_input(principal: Principal) -> Vec<u8> {
+    principal.as_slice().to_vec()
+}
+
+pub fn note_key_input(note_id: u64) -> Vec<u8> {
+    note_id.to_be_bytes().to_vec()
+}
+
+pub async fn scoped_public_key(deriver: &impl KeyDeriver, scope: KeyScope) -> Result<Vec<u8>, String> {
+    deriver.public_key(scope.context()).await
+}
+
+pub async fn derive_scoped_key(
+    deriver: &impl KeyDeriver,
+    scope: KeyScope,
+    input: &[u8],
+    transport_public_key: &[u8],
+) -> Result<Vec<u8>, String> {
+    if transport_public_key.is_empty() || transport_public_key.len() > MAX_TRANSPORT_KEY_BYTES {
+        return Err(format!(
+            "Transport public key must be between 1 and {} bytes.",
+            MAX_TRANSPORT_KEY_BYTES
+        ));
+    }
+    deriver.derive_key(scope.context(), input, transport_public_key).await
+}
+
+// --- Threshold vetKD ---
+#[derive(CandidType, Deserialize)]
+enum VetKdCurve {
+    #[serde(rename = "bls12_381_g2")]
+    Bls12381G2,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VetKdKeyId {
+    curve: VetKdCurve,
+    name: String,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VetKdPublicKeyArgs {
+    canister_id: Option<Principal>,
+    context: Vec<u8>,
+    key_id: VetKdKeyId,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VetKdPublicKeyResult {
+    public_key: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VetKdDeriveKeyArgs {
+    input: Vec<u8>,
+    context: Vec<u8>,
+    transport_public_key: Vec<u8>,
+    key_id: VetKdKeyId,
+}
+
+#[derive(CandidType, Deserialize)]
+struct VetKdDeriveKeyResult {
+    encrypted_key: Vec<u8>,
+}
+
+/// Calls the management canister's vetKD methods with the named subnet key.
+pub struct VetKdDeriver {
+    pub key_name: String,
+}
+
+impl VetKdDeriver {
+    fn key_id(&self) -> VetKdKeyId {
+        VetKdKeyId {
+            curve: VetKdCurve::Bls12381G2,
+            name: self.key_name.clone(),
+        }
+    }
+
+    fn derive_fee(&self) -> u128 {
+        if self.key_name.starts_with("test_") || self.key_name.starts_with("dfx_") {
+            VETKD_TEST_KEY_FEE
+        } else {
+            VETKD_PRODUCTION_KEY_FEE
+        }
+    }
+}
+
+impl KeyDeriver for VetKdDeriver {
+    async fn public_key(&self, context: &[u8]) -> Result<Vec<u8>, String> {
+        let args = VetKdPublicKeyArgs {
+            canister_id: None,
+            context: context.to_vec(),
+            key_id: self.key_id(),
+        };
+        let (result,): (VetKdPublicKeyResult,) =
+            ic_cdk::call(Principal::management_canister(), "vetkd_public_key", (args,))
+                .await
+                .map_err(|(code, message)| format!("vetkd_public_key failed ({:?}): {}", code, message))?;
+        Ok(result.public_key)
+    }
+
+    async fn derive_key(&self, context: &[u8], input: &[u8], transport_public_key: &[u8]) -> Result<Vec<u8>, String> {
+        let args = VetKdDeriveKeyArgs {
+            input: input.to_vec(),
+            context: context.to_vec(),
+            transport_public_key: transport_public_key.to_vec(),
+            key_id: self.key_id(),
+        };
+        let (result,): (VetKdDeriveKeyResult,) = ic_cdk::api::call::call_with_payment128(
+            Principal::management_canister(),
+            "vetkd_derive_key",
+            (args,),
+            self.derive_fee(),
+        )
+        .await
+        .map_err(|(code, message)| format!("vetkd_derive_key failed ({:?}): {}", code, message))?;
+        Ok(result.encrypted_key)
+    }
+}
+
+// --- Local Stand-In ---
+/// Deterministic keys computed from a seed with SHA-256. Anyone who knows the
+/// seed can recompute every key, and the "encryption" under the transport key
+/// is only a keyed XOR, so this is for tests and local replicas without vetKD.
+pub struct LocalKeyDeriver {
+    pub seed: Vec<u8>,
+}
+
+impl LocalKeyDeriver {
+    fn hash(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.seed);
+        for part in parts {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+
+    /// The key `derive_key` returns before it is masked for transport.
+    pub fn raw_key(&self, context: &[u8], input: &[u8]) -> [u8; 32] {
+        self.hash(&[b"key", context, input])
+    }
+
+    /// Undoes the transport masking of a key returned by `derive_key`.
+    pub fn unmask(encrypted_key: &[u8], transport_public_key: &[u8]) -> Vec<u8> {
+        let mask: [u8; 32] = Sha256::digest(transport_public_key).into();
+        encrypted_key.iter().zip(mask).map(|(byte, m)| byte ^ m).collect()
+    }
+}
+
+impl KeyDeriver for LocalKeyDeriver {
+    async fn public_key(&self, context: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(self.hash(&[b"public", context]).to_vec())
+    }
+
+    async fn derive_key(&self, context: &[u8], input: &[u8], transport_public_key: &[u8]) -> Result<Vec<u8>, String> {
+        // XOR is its own inverse, so masking and unmasking are the same operation.
+        Ok(Self::unmask(&self.raw_key(context, input), transport_public_key))
+    }
+}
+
+/// The deriver selected by the configuration.
+pub enum ConfiguredDeriver {
+    VetKd(VetKdDeriver),
+    Local(LocalKeyDeriver),
+}
+
+impl ConfiguredDeriver {
+    pub fn new(derivation: &KeyDerivation, canister_id: Principal) -> Self {
+        match derivation {
+            KeyDerivation::VetKd { key_name } => ConfiguredDeriver::VetKd(VetKdDeriver {
+                key_name: key_name.clone(),
+            }),
+            KeyDerivation::InsecureLocal => ConfiguredDeriver::Local(LocalKeyDeriver {
+                seed: canister_id.as_slice().to_vec(),
+            }),
+        }
+    }
+}
+
+impl KeyDeriver for ConfiguredDeriver {
+    async fn public_key(&self, context: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            ConfiguredDeriver::VetKd(deriver) => deriver.public_key(context).await,
+            ConfiguredDeriver::Local(deriver) => deriver.public_key(context).await,
+        }
+    }
+
+    async fn derive_key(&self, context: &[u8], input: &[u8], transport_public_key: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            ConfiguredDeriver::VetKd(deriver) => deriver.derive_key(context, input, transport_public_key).await,
+            ConfiguredDeriver::Local(deriver) => deriver.derive_key(context, input, transport_public_key).await,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+
+    /// Polls a future that never waits, such as one driven by `LocalKeyDeriver`.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("future is not ready"),
+        }
+    }
+
+    #[test]
+    fn test_local_keys_are_deterministic_and_scoped() {
+        let deriver = LocalKeyDeriver { seed: vec![1, 2, 3] };
+        let transport = vec![9; 48];
+        let alice = user_key_input(Principal::from_slice(&[1; 29]));
+
+        let first = block_on(derive_scoped_key(&deriver, KeyScope::User, &alice, &transport)).unwrap();
+        let again = block_on(derive_scoped_key(&deriver, KeyScope::User, &alice, &transport)).unwrap();
+        assert_eq!(first, again);
+        assert_eq!(
+            LocalKeyDeriver::unmask(&first, &transport),
+            deriver.raw_key(USER_KEY_CONTEXT, &alice).to_vec()
+        );
+
+        let note = block_on(derive_scoped_key(&deriver, KeyScope::Note, &alice, &transport)).unwrap();
+        assert_ne!(first, note);
+        let other_seed = LocalKeyDeriver { seed: vec![4] };
+        assert_ne!(block_on(derive_scoped_key(&other_seed, KeyScope::User, &alice, &transport)).unwrap(), first);
+
+        assert!(block_on(derive_scoped_key(&deriver, KeyScope::User, &alice, &[])).is_err());
+    }
+}
@@ -1,9 +1,64 @@
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk_macros::*;
 use ic_cdk::api::{caller, time};
+use ic_cdk::api::stable::{CanisterStableMemory, StableWriter};
 use ic_cdk::storage;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::cell::RefCell;
+use std::time::Duration;
+use ic_cdk_timers::TimerId;
+
+mod admin;
+mod audit;
+mod batch;
+mod blobs;
+mod checklist;
+mod comments;
+mod config;
+mod csv;
+mod datetime;
+mod encryption;
+mod enex;
+mod export;
+mod import;
+mod inbox;
+mod keys;
+mod links;
+mod markdown;
+mod mentions;
+mod note_state;
+mod notion;
+mod properties;
+mod query;
+mod quota;
+mod ratelimit;
+mod templates;
+mod validation;
+mod zip;
+
+use admin::{AdminAction, AdminLogEntry, AdminLogPage, GlobalStats, Suspension};
+use audit::{AuditAction, AuditChainHead, AuditCheckpoint, AuditEntry, AuditLog, AuditPage, LogMemory};
+use blobs::BlobStore;
+use checklist::{Checklist, NewChecklistItem, OpenTask};
+use comments::{Comment, Comments};
+use batch::{BatchError, BatchOp, BatchOpError, BatchOpResult, MAX_BATCH_OPS};
+use encryption::{EncryptedContent, WrappedKey};
+use config::{AnonymousPolicy, CanisterConfig, ConfigArgs, Quota};
+use export::{ExportChunk, ExportFormat};
+use inbox::{Inboxes, NotificationKind, NotificationPage};
+use import::{
+    ImportBatchReport, ImportItem, ImportItemReport, ImportJob, ImportSource, NoteDraft, MAX_IMPORT_BATCH,
+    MAX_IMPORT_JOBS_PER_OWNER,
+};
+use note_state::{NoteColor, NoteState, NoteStateEntry, NoteStates};
+use properties::{Properties, PropertyCondition, PropertySchema, PropertyValue};
+use query::{Candidate, NoteQuery, NoteQueryPage};
+use quota::{UsageDelta, UsageReport};
+use links::{GraphNode, LinkEdge, LinkGraph, LinkIndex, LinkTarget};
+use mentions::MentionIndex;
+use keys::{ConfiguredDeriver, KeyScope};
+use templates::{Template, TemplateInput, TemplateVariable, Templates};
+use ratelimit::{RateLimitError, RateLimitedMethod, RateLimiter, WriteError};
 
 // --- Data Structures ---
 #[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)] // Added serde::Serialize for stable storage
@@ -13,6 +68,52 @@ struct Note {
     title: String,
     content: String,
     created_at: u64, // nanoseconds from epoch
+    tags: Vec<String>,
+    updated_at: Option<u64>, // None until the note is first edited
+    expires_at: Option<u64>, // set for ephemeral anonymous notes
+    encrypted: Option<EncryptedContent>, // title and content are empty when set
+    content_hash: Option<Vec<u8>>, // SHA-256 of content; None for encrypted notes
+    remind_at: Option<u64>, // pending reminder for the owner, cleared once it fires
+    checklist: Option<Checklist>, // set for checklist notes; content is the description
+    properties: Option<Properties>, // typed metadata; None rather than an empty map
+}
+
+// Note layout used by storage version 1, before tags were introduced.
+#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
+struct NoteV1 {
+    id: u64,
+    owner: Principal,
+    title: String,
+    content: String,
+    created_at: u64,
+}
+
+impl From<NoteV1> for Note {
+    fn from(note: NoteV1) -> Self {
+        Note {
+            id: note.id,
+            owner: note.owner,
+            title: note.title,
+            content: note.content,
+            created_at: note.created_at,
+            tags: Vec::new(),
+            updated_at: None,
+            expires_at: None,
+            encrypted: None,
+            content_hash: None,
+            remind_at: None,
+            checklist: None,
+            properties: None,
+        }
+    }
+}
+
+/// Per-id outcome of `get_notes_by_ids`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+enum NoteLookup {
+    Found(Box<Note>),
+    NotFound,
+    Forbidden,
 }
 
 // --- State ---
@@ -21,11 +122,48 @@ type NoteStore = HashMap<u64, Note>;
 thread_local! {
     // Using RefCell for interior mutability, common in ICP canisters
     static NOTES: RefCell<NoteStore> = RefCell::new(HashMap::new());
-    static NEXT_ID: RefCell<u64> = RefCell::new(1); // Start IDs from 1
+    static NEXT_ID: RefCell<u64> = const { RefCell::new(1) }; // Start IDs from 1
+    // Import jobs are transient and intentionally not saved across upgrades
+    static IMPORT_JOBS: RefCell<HashMap<u64, ImportJob>> = RefCell::new(HashMap::new());
+    static NEXT_IMPORT_JOB_ID: RefCell<u64> = const { RefCell::new(1) };
+    static CONFIG: RefCell<CanisterConfig> = RefCell::new(CanisterConfig::default());
+    // Per-principal quotas that replace the configured default
+    static QUOTA_OVERRIDES: RefCell<HashMap<Principal, Quota>> = RefCell::new(HashMap::new());
+    // (expires_at, note id) for ephemeral notes; rebuilt from NOTES after upgrade
+    static EXPIRY_INDEX: RefCell<BTreeSet<(u64, u64)>> = const { RefCell::new(BTreeSet::new()) };
+    // Rate limit buckets are transient; an upgrade gives every caller a full bucket
+    static RATE_LIMITER: RefCell<RateLimiter> = RefCell::new(RateLimiter::default());
+    // Admins in addition to the controllers, who are always admins
+    static ADMINS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static SUSPENDED: RefCell<HashMap<Principal, Suspension>> = RefCell::new(HashMap::new());
+    static ADMIN_LOG: RefCell<Vec<AdminLogEntry>> = const { RefCell::new(Vec::new()) };
+    // Append-only and hash-chained, kept in stable memory; see audit.rs
+    static AUDIT_LOG: RefCell<AuditLog<LogMemory>> = RefCell::new(AuditLog::new(LogMemory::default()));
+    // Stored notes keep their content here, keyed by `content_hash`
+    static BLOBS: RefCell<BlobStore> = RefCell::new(BlobStore::default());
+    // (remind_at, note id) for pending reminders; rebuilt from NOTES after upgrade
+    static REMINDER_INDEX: RefCell<BTreeSet<(u64, u64)>> = const { RefCell::new(BTreeSet::new()) };
+    // One-shot timer for the earliest reminder; timers do not survive upgrades
+    static REMINDER_TIMER: RefCell<Option<TimerId>> = const { RefCell::new(None) };
+    static INBOXES: RefCell<Inboxes> = RefCell::new(Inboxes::default());
+    static COMMENTS: RefCell<Comments> = RefCell::new(Comments::default());
+    static MENTIONS: RefCell<MentionIndex> = RefCell::new(MentionIndex::default());
+    // Unresolved `[[...]]` targets per source note; see links.rs
+    static LINKS: RefCell<LinkIndex> = RefCell::new(LinkIndex::default());
+    static TEMPLATES: RefCell<Templates> = RefCell::new(Templates::default());
+    // Pinned/favorite/archived/color, per viewer
+    static NOTE_STATES: RefCell<NoteStates> = RefCell::new(NoteStates::default());
+    // Declared property types per owner; owners without one may use any key
+    static PROPERTY_SCHEMAS: RefCell<BTreeMap<Principal, PropertySchema>> = const { RefCell::new(BTreeMap::new()) };
+    // Owners of removed notes, who may still read those notes' audit entries
+    static REMOVED_NOTE_OWNERS: RefCell<BTreeMap<u64, Principal>> = const { RefCell::new(BTreeMap::new()) };
 }
 
 // --- Constants ---
-const MAX_NOTE_SIZE_BYTES: usize = 1024; // 1KB limit for title + content
+const MAX_TAGS_PER_NOTE: usize = 20;
+const MAX_TAG_LENGTH: usize = 32;
+const MAX_NOTES_BY_IDS: usize = 100;
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
 
 // --- Helper Functions ---
 fn get_next_id() -> u64 {
@@ -36,202 +174,2169 @@ fn get_next_id() -> u64 {
     })
 }
 
-// --- Versioned Storage for Safe Migrations ---
-#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
-struct StorageV1 {
-    notes: NoteStore,
-    next_id: u64,
-    version: u32,
+/// Whether `reader` may see `note`. Plain notes are shared by link, so any
+/// caller who knows an id may read them, matching `get_note_by_id`. Encrypted
+/// notes are only returned to their owner and the recipients of a wrapped key.
+fn can_read(note: &Note, reader: Principal) -> bool {
+    match &note.encrypted {
+        Some(encrypted) => note.owner == reader || encrypted.has_recipient(reader),
+        None => true,
+    }
 }
 
-// --- Canister Lifecycle Hooks for Stable Storage ---
-#[pre_upgrade]
-fn pre_upgrade() {
-    // Use defensive programming to prevent upgrade failures
-    let save_result = std::panic::catch_unwind(|| {
-        let combined_state = StorageV1 {
-            notes: NOTES.with(|notes_cell| notes_cell.borrow().clone()),
-            next_id: NEXT_ID.with(|next_id_cell| *next_id_cell.borrow()),
-            version: 1,
-        };
+/// Content of a stored note. Notes with a `content_hash` keep their content in
+/// BLOBS; notes without one (encrypted or not yet migrated) keep it inline.
+fn stored_content(note: &Note) -> String {
+    match &note.content_hash {
+        Some(hash) => BLOBS.with(|blobs_cell| {
+            String::from_utf8_lossy(blobs_cell.borrow().get(hash).unwrap_or_default()).into_owned()
+        }),
+        None => note.content.clone(),
+    }
+}
 
-        storage::stable_save((combined_state,))
-    });
+fn content_len(note: &Note) -> usize {
+    match &note.content_hash {
+        Some(hash) => BLOBS.with(|blobs_cell| blobs_cell.borrow().get(hash).map_or(0, <[u8]>::len)),
+        None => note.content.len(),
+    }
+}
 
-    match save_result {
-        Ok(Ok(())) => {
-            ic_cdk::print("Successfully saved state to stable storage");
-        },
-        Ok(Err(e)) => {
-            ic_cdk::print(&format!("Warning: Failed to save state to stable storage: {:?}. Upgrade will proceed with empty state.", e));
-        },
-        Err(_) => {
-            ic_cdk::print("Warning: Panic during save to stable storage. Upgrade will proceed with empty state.");
+/// A copy of a stored note with its content filled in, as returned to callers.
+fn hydrate(note: &Note) -> Note {
+    Note {
+        content: stored_content(note),
+        ..note.clone()
+    }
+}
+
+/// Moves `content` into the blob store and returns its hash.
+fn intern_content(content: &str) -> Vec<u8> {
+    BLOBS.with(|blobs_cell| blobs_cell.borrow_mut().put(content.as_bytes()))
+}
+
+/// Drops a note's reference to its content.
+fn release_content(note: &Note) {
+    if let Some(hash) = &note.content_hash {
+        BLOBS.with(|blobs_cell| blobs_cell.borrow_mut().release(hash));
+    }
+}
+
+/// Frees what a removed note leaves behind: its content, comments, mentions,
+/// outgoing links and viewer states. Links pointing to it become broken.
+fn release_note(note: &Note) {
+    release_content(note);
+    REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow_mut().insert(note.id, note.owner));
+    NOTE_STATES.with(|states_cell| states_cell.borrow_mut().remove_note(note.id));
+    COMMENTS.with(|comments_cell| comments_cell.borrow_mut().remove_note(note.id));
+    MENTIONS.with(|mentions_cell| mentions_cell.borrow_mut().remove_note(note.id));
+    LINKS.with(|links_cell| links_cell.borrow_mut().remove_source(note.id));
+}
+
+/// Records the mentions and links in a stored note's plaintext content and
+/// notifies newly mentioned principals who can read the note.
+fn index_content(author: Principal, now: u64, id: u64, content: &str) {
+    LINKS.with(|links_cell| links_cell.borrow_mut().set(id, links::parse_links(content)));
+    let added = MENTIONS.with(|mentions_cell| mentions_cell.borrow_mut().set(id, mentions::parse_mentions(content)));
+    for principal in added {
+        if principal != author && readable_note_owner(principal, id).is_ok() {
+            let kind = NotificationKind::Mention { note_id: id, by: author };
+            INBOXES.with(|inboxes_cell| inboxes_cell.borrow_mut().push(principal, now, kind));
         }
     }
 }
 
-#[post_upgrade]
-fn post_upgrade() {
-    // Initialize with empty state first to ensure we have a valid state
+/// Interns inline content of notes restored from before the blob store existed.
+fn intern_inline_contents() {
     NOTES.with(|notes_cell| {
-        *notes_cell.borrow_mut() = HashMap::new();
-    });
-    NEXT_ID.with(|next_id_cell| {
-        *next_id_cell.borrow_mut() = 1;
-    });
-
-    // Use defensive programming to handle any possible corruption or format incompatibility
-    let restore_result = std::panic::catch_unwind(|| {
-        // Try to restore new versioned format first
-        if let Ok((storage_v1,)) = storage::stable_restore::<(StorageV1,)>() {
-            if storage_v1.version == 1 {
-                return Some((storage_v1.notes, storage_v1.next_id));
+        for note in notes_cell.borrow_mut().values_mut() {
+            if note.content_hash.is_none() && note.encrypted.is_none() {
+                note.content_hash = Some(intern_content(&note.content));
+                note.content = String::new();
             }
         }
+    });
+}
 
-        // Try to restore old format (tuple) as fallback
-        if let Ok((restored_notes, restored_next_id)) = storage::stable_restore::<(NoteStore, u64)>() {
-            return Some((restored_notes, restored_next_id));
-        }
+fn lookup_notes(reader: Principal, ids: &[u64]) -> Result<Vec<NoteLookup>, String> {
+    if ids.len() > MAX_NOTES_BY_IDS {
+        return Err(format!("Cannot fetch more than {} notes per call.", MAX_NOTES_BY_IDS));
+    }
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        Ok(ids
+            .iter()
+            .map(|id| match notes.get(id) {
+                Some(note) if can_read(note, reader) => NoteLookup::Found(Box::new(hydrate(note))),
+                Some(_) => NoteLookup::Forbidden,
+                None => NoteLookup::NotFound,
+            })
+            .collect())
+    })
+}
 
-        None
-    });
+/// Cleans up a plaintext title and content according to the configured
+/// validation policy and checks them. Returns the text to store.
+fn normalize_note(title: &str, content: &str) -> Result<(String, String), String> {
+    let policy = CONFIG.with(|config_cell| config_cell.borrow().validation);
+    Ok(validation::normalize_note(&policy, max_note_size_bytes(), title, content)?)
+}
 
-    match restore_result {
-        Ok(Some((restored_notes, restored_next_id))) => {
-            NOTES.with(|notes_cell| {
-                *notes_cell.borrow_mut() = restored_notes;
-            });
-            NEXT_ID.with(|next_id_cell| {
-                *next_id_cell.borrow_mut() = restored_next_id;
+fn max_note_size_bytes() -> usize {
+    CONFIG.with(|config_cell| config_cell.borrow().max_note_size_bytes as usize)
+}
+
+fn validate_note(title: &str, content: &str) -> Result<(), String> {
+    normalize_note(title, content).map(|_| ())
+}
+
+/// Trims tags, drops blanks and duplicates, and enforces the tag limits.
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || normalized.iter().any(|t| t == tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(format!("Tag '{}' exceeds {} characters.", tag, MAX_TAG_LENGTH));
+        }
+        normalized.push(tag.to_string());
+    }
+    if normalized.len() > MAX_TAGS_PER_NOTE {
+        return Err(format!("A note can have at most {} tags.", MAX_TAGS_PER_NOTE));
+    }
+    Ok(normalized)
+}
+
+/// Checks the anonymous policy before `caller` writes, creating `new_notes` notes.
+fn authorize_write(caller_principal: Principal, new_notes: usize) -> Result<(), String> {
+    if let Some(suspension) = SUSPENDED.with(|suspended_cell| suspended_cell.borrow().get(&caller_principal).cloned()) {
+        return Err(format!("Your account is suspended: {}", suspension.reason));
+    }
+    if caller_principal != Principal::anonymous() {
+        return Ok(());
+    }
+    match CONFIG.with(|config_cell| config_cell.borrow().anonymous_policy) {
+        AnonymousPolicy::Disallow => {
+            Err("Anonymous users cannot modify notes. Please sign in.".to_string())
+        }
+        AnonymousPolicy::AllowWithSeparateQuota { max_notes } => {
+            let existing = NOTES.with(|notes_cell| {
+                notes_cell
+                    .borrow()
+                    .values()
+                    .filter(|note| note.owner == caller_principal)
+                    .count() as u64
             });
-            ic_cdk::print(&format!("Successfully restored {} notes after upgrade", 
-                NOTES.with(|notes_cell| notes_cell.borrow().len())));
-        },
-        Ok(None) => {
-            ic_cdk::print("No valid state found in stable storage, starting with empty state");
-        },
-        Err(_) => {
-            ic_cdk::print("Error during state restoration, starting with empty state");
+            if existing + new_notes as u64 > max_notes {
+                return Err(format!(
+                    "The shared anonymous note limit of {} has been reached. Please sign in.",
+                    max_notes
+                ));
+            }
+            Ok(())
         }
+        AnonymousPolicy::AllowEphemeral { .. } => Ok(()),
     }
 }
 
-// --- Public Update Calls ---
+fn is_admin(principal: Principal) -> bool {
+    is_controller(principal) || ADMINS.with(|admins_cell| admins_cell.borrow().contains(&principal))
+}
 
-#[update]
-fn create_note(title: String, content: String) -> Result<u64, String> {
-    let owner = caller();
-    // Allow anonymous users for demo purposes
-    // In production, you might want to restrict this
-    
-    if title.is_empty() {
-        return Err("Title cannot be empty.".to_string());
+#[cfg(not(test))]
+fn is_controller(principal: Principal) -> bool {
+    ic_cdk::api::is_controller(&principal)
+}
+
+/// Native tests have no controllers system API; they list controllers themselves.
+#[cfg(test)]
+fn is_controller(principal: Principal) -> bool {
+    tests::CONTROLLERS.with(|controllers_cell| controllers_cell.borrow().contains(&principal))
+}
+
+fn require_admin() -> Result<Principal, String> {
+    let caller_principal = caller();
+    if !is_admin(caller_principal) {
+        return Err("Only admins can perform this action.".to_string());
     }
-    if content.is_empty() {
-        return Err("Content cannot be empty.".to_string());
+    Ok(caller_principal)
+}
+
+fn record_admin_action(admin: Principal, now: u64, action: AdminAction) {
+    ADMIN_LOG.with(|log_cell| {
+        let mut log = log_cell.borrow_mut();
+        let seq = log.last().map_or(1, |entry| entry.seq + 1);
+        log.push(AdminLogEntry { seq, admin, at: now, action });
+    });
+}
+
+/// Appends a note mutation to the audit log. `content` is the note content after the change.
+fn record_audit(caller_principal: Principal, action: AuditAction, note_id: u64, now: u64, content: Option<&[u8]>) {
+    record_audit_for(caller_principal, action, note_id, now, None, content);
+}
+
+/// Like `record_audit`, naming the principal a share or unshare was about.
+fn record_audit_for(
+    caller_principal: Principal,
+    action: AuditAction,
+    note_id: u64,
+    now: u64,
+    recipient: Option<Principal>,
+    content: Option<&[u8]>,
+) {
+    let content_hash = content.map(audit::content_hash);
+    AUDIT_LOG.with(|log_cell| {
+        log_cell.borrow_mut().append(caller_principal, action, note_id, now, content_hash, recipient)
+    });
+}
+
+fn quota_for(owner: Principal) -> Quota {
+    QUOTA_OVERRIDES
+        .with(|overrides_cell| overrides_cell.borrow().get(&owner).copied())
+        .unwrap_or_else(|| CONFIG.with(|config_cell| config_cell.borrow().default_quota))
+}
+
+/// Checks that a write changing `owner`'s usage by `delta` stays within their quota.
+fn authorize_usage(owner: Principal, delta: UsageDelta) -> Result<(), String> {
+    let usage = NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner));
+    quota::check_quota(usage, quota_for(owner), delta).map(|_| ())
+}
+
+/// Expiry time for a note `owner` creates now, under the current policy.
+fn ephemeral_expiry(owner: Principal, now: u64) -> Option<u64> {
+    if owner != Principal::anonymous() {
+        return None;
+    }
+    match CONFIG.with(|config_cell| config_cell.borrow().anonymous_policy) {
+        AnonymousPolicy::AllowEphemeral { ttl_seconds } => {
+            Some(now.saturating_add(Duration::from_secs(ttl_seconds).as_nanos() as u64))
+        }
+        _ => None,
     }
+}
 
-    if title.len() + content.len() > MAX_NOTE_SIZE_BYTES {
-        return Err(format!(
-            "Note (title + content) exceeds {} byte limit. Current size: {}",
-            MAX_NOTE_SIZE_BYTES,
-            title.len() + content.len()
-        ))
+/// Deletes ephemeral notes whose expiry time has passed, auditing them as `sweeper`.
+fn purge_expired_notes(sweeper: Principal, now: u64) {
+    let expired: Vec<(u64, u64)> = EXPIRY_INDEX.with(|index_cell| {
+        let mut index = index_cell.borrow_mut();
+        let still_live = index.split_off(&(now.saturating_add(1), 0));
+        std::mem::replace(&mut *index, still_live).into_iter().collect()
+    });
+    if expired.is_empty() {
+        return;
     }
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        for (_, id) in expired {
+            if let Some(note) = notes.remove(&id) {
+                release_note(&note);
+                record_audit(sweeper, AuditAction::Expire, id, now, None);
+            }
+        }
+    });
+}
 
-    let new_id = get_next_id();
-    let current_time = time();
+fn rebuild_expiry_index() {
+    let index: BTreeSet<(u64, u64)> = NOTES.with(|notes_cell| {
+        notes_cell
+            .borrow()
+            .values()
+            .filter_map(|note| note.expires_at.map(|expires_at| (expires_at, note.id)))
+            .collect()
+    });
+    EXPIRY_INDEX.with(|index_cell| *index_cell.borrow_mut() = index);
+}
 
-    let note = Note {
-        id: new_id,
-        owner,
-        title,
-        content,
-        created_at: current_time,
-    };
+/// Recovers the owners of removed notes from the audit log, for states saved
+/// before they were kept. A note's owner is the caller of its `Create` entry.
+fn rebuild_removed_note_owners() {
+    let owners: BTreeMap<u64, Principal> = AUDIT_LOG.with(|log_cell| {
+        let log = log_cell.borrow();
+        (1..=log.len())
+            .filter_map(|seq| log.get(seq))
+            .filter(|entry| entry.action == AuditAction::Create)
+            .map(|entry| (entry.note_id, entry.caller))
+            .collect()
+    });
+    let live = NOTES.with(|notes_cell| notes_cell.borrow().keys().copied().collect::<HashSet<u64>>());
+    let removed = owners.into_iter().filter(|(id, _)| !live.contains(id)).collect();
+    REMOVED_NOTE_OWNERS.with(|owners_cell| *owners_cell.borrow_mut() = removed);
+}
 
-    NOTES.with(|notes_cell| {
-        notes_cell.borrow_mut().insert(new_id, note);
+/// Spends rate limit tokens for `caller`, or none if any method is over its limit.
+fn consume_rate_limit(
+    caller_principal: Principal,
+    now: u64,
+    costs: &[(RateLimitedMethod, u32)],
+) -> Result<(), RateLimitError> {
+    let limits = CONFIG.with(|config_cell| config_cell.borrow().rate_limits);
+    RATE_LIMITER.with(|limiter_cell| limiter_cell.borrow_mut().try_acquire(&limits, caller_principal, now, costs))
+}
+
+/// Whole tokens `caller` could spend on `method` right now.
+fn rate_limit_tokens(caller_principal: Principal, now: u64, method: RateLimitedMethod) -> u32 {
+    let limits = CONFIG.with(|config_cell| config_cell.borrow().rate_limits);
+    RATE_LIMITER.with(|limiter_cell| limiter_cell.borrow().tokens(&limits, caller_principal, now, method))
+}
+
+fn start_expiry_sweeper() {
+    ic_cdk_timers::set_timer_interval(EXPIRY_SWEEP_INTERVAL, || {
+        let now = time();
+        purge_expired_notes(ic_cdk::id(), now);
+        drop_idle_import_jobs(now);
+        let limits = CONFIG.with(|config_cell| config_cell.borrow().rate_limits);
+        RATE_LIMITER.with(|limiter_cell| limiter_cell.borrow_mut().prune(&limits, now));
     });
+}
 
-    Ok(new_id)
+/// Abandoned jobs would otherwise hold their share of the import memory
+/// limit, which all owners share, until the next upgrade.
+fn drop_idle_import_jobs(now: u64) {
+    IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().retain(|_, job| !job.is_idle(now)));
 }
 
-#[update]
-fn update_note(id: u64, title: String, content: String) -> Result<(), String> {
-    let caller_principal = caller();
-    
-    if title.is_empty() {
-        return Err("Title cannot be empty.".to_string());
-    }
-    if content.is_empty() {
-        return Err("Content cannot be empty.".to_string());
-    }
-    if title.len() + content.len() > MAX_NOTE_SIZE_BYTES {
-        return Err(format!(
-            "Note (title + content) exceeds {} byte limit. Current size: {}",
-            MAX_NOTE_SIZE_BYTES,
-            title.len() + content.len()
-        ));
+/// Applies `change` to `viewer`'s own state for a note they can read.
+fn update_note_state_as(viewer: Principal, id: u64, change: impl FnOnce(&mut NoteState)) -> Result<(), String> {
+    readable_note_owner(viewer, id)?;
+    NOTE_STATES.with(|states_cell| states_cell.borrow_mut().update(viewer, id, change))
+}
+
+/// A default listing for `viewer`: archived notes are left out and pinned
+/// notes come first, each group by ascending id.
+fn list_for_viewer<'a>(viewer: Principal, notes: impl Iterator<Item = &'a Note>) -> Vec<Note> {
+    NOTE_STATES.with(|states_cell| {
+        let states = states_cell.borrow();
+        let mut listed: Vec<(bool, &Note)> = notes
+            .map(|note| (states.get(viewer, note.id), note))
+            .filter(|(state, _)| !state.archived)
+            .map(|(state, note)| (state.pinned, note))
+            .collect();
+        listed.sort_by_key(|(pinned, note)| (!pinned, note.id));
+        listed.into_iter().map(|(_, note)| hydrate(note)).collect()
+    })
+}
+
+/// Checks a template like a note, except that placeholders stay unexpanded.
+fn validate_template(input: TemplateInput) -> Result<TemplateInput, String> {
+    let name = templates::validate_name(&input.name)?;
+    let (title, content) = normalize_note(&input.title, &input.content)?;
+    let tags = normalize_tags(input.tags)?;
+    Ok(TemplateInput { name, title, content, tags })
+}
+
+fn create_note_from_template_as(
+    author: Principal,
+    now: u64,
+    template_id: u64,
+    variables: Vec<TemplateVariable>,
+) -> Result<u64, String> {
+    let template = TEMPLATES.with(|templates_cell| templates_cell.borrow().owned(author, template_id).cloned())?;
+    let values = templates::placeholder_values(author, now, variables)?;
+    let (title, content) = normalize_note(
+        &templates::render(&template.title, &values),
+        &templates::render(&template.content, &values),
+    )?;
+    authorize_usage(author, UsageDelta::create(quota::content_bytes(&title, &content, &template.tags)))?;
+    let mut draft = NoteDraft::new(title, content);
+    draft.tags = template.tags;
+    Ok(insert_note(author, now, draft))
+}
+
+fn create_checklist_note_as(
+    owner: Principal,
+    now: u64,
+    title: String,
+    description: String,
+    items: Vec<NewChecklistItem>,
+) -> Result<u64, String> {
+    let (title, content) = normalize_note(&title, &description)?;
+    let mut checklist = Checklist::default();
+    for item in items {
+        checklist.add_item(item)?;
     }
+    let bytes = quota::content_bytes(&title, &content, &[]) + checklist.bytes();
+    authorize_usage(owner, UsageDelta::create(bytes))?;
+    let id = insert_note(owner, now, NoteDraft::new(title, content));
+    NOTES.with(|notes_cell| notes_cell.borrow_mut().get_mut(&id).expect("note was just stored").checklist = Some(checklist));
+    Ok(id)
+}
 
+/// Applies `change` to a copy of a checklist and stores the result if
+/// the owner's quota allows it. Only the owner may change a checklist, except
+/// that the assignee of `assigned_item` may change that item.
+fn change_checklist<R>(
+    caller_principal: Principal,
+    now: u64,
+    id: u64,
+    assigned_item: Option<u64>,
+    change: impl FnOnce(&mut Checklist) -> Result<R, String>,
+) -> Result<R, String> {
     NOTES.with(|notes_cell| {
         let mut notes = notes_cell.borrow_mut();
-        if let Some(note) = notes.get_mut(&id) {
-            // Check if the caller owns this note
-            if note.owner != caller_principal {
-                return Err("You can only update your own notes.".to_string());
-            }
-            note.title = title;
-            note.content = content;
-            Ok(())
-        } else {
-            Err("Note not found.".to_string())
+        let note = notes.get(&id).filter(|note| can_read(note, caller_principal)).ok_or("Note not found.")?;
+        let current = note.checklist.as_ref().ok_or("Note is not a checklist.")?;
+        let is_assignee = assigned_item.is_some_and(|item_id| {
+            current.items.iter().any(|item| item.id == item_id && item.assignee == Some(caller_principal))
+        });
+        if note.owner != caller_principal && !is_assignee {
+            return Err("You can only change your own checklists.".to_string());
         }
+        let mut changed = current.clone();
+        let result = change(&mut changed)?;
+
+        let delta = changed.bytes() as i64 - current.bytes() as i64;
+        let owner = note.owner;
+        quota::check_quota(quota::usage_of(&notes, owner), quota_for(owner), UsageDelta::resize(delta))?;
+        let audited = serde_json::to_vec(&changed.items).map_err(|e| format!("Failed to serialize checklist: {}", e))?;
+        record_audit(caller_principal, AuditAction::Update, id, now, Some(&audited));
+        let note = notes.get_mut(&id).expect("note exists");
+        note.checklist = Some(changed);
+        note.updated_at = Some(now);
+        Ok(result)
     })
 }
 
-#[update]
-fn delete_note(id: u64) -> Result<(), String> {
-    let caller_principal = caller();
-    
+/// Sets (`Some`) or removes (`None`) one property on one of `owner`'s notes.
+fn set_note_property_as(
+    owner: Principal,
+    now: u64,
+    id: u64,
+    key: String,
+    value: Option<PropertyValue>,
+) -> Result<(), String> {
+    let schema = PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow().get(&owner).cloned());
     NOTES.with(|notes_cell| {
         let mut notes = notes_cell.borrow_mut();
-        if let Some(note) = notes.get(&id) {
-            // Check if the caller owns this note
-            if note.owner != caller_principal {
-                return Err("You can only delete your own notes.".to_string());
+        let note = notes.get(&id).filter(|note| can_read(note, owner)).ok_or("Note not found.")?;
+        if note.owner != owner {
+            return Err("You can only change properties of your own notes.".to_string());
+        }
+        let current = note.properties.clone().unwrap_or_default();
+        let mut changed = current.clone();
+        match value {
+            Some(value) => {
+                properties::validate_property(&key, &value, schema.as_ref())?;
+                changed.insert(key, value);
+                if changed.len() > properties::MAX_PROPERTIES_PER_NOTE {
+                    return Err(format!(
+                        "A note can have at most {} properties.",
+                        properties::MAX_PROPERTIES_PER_NOTE
+                    ));
+                }
             }
-            notes.remove(&id);
-            Ok(())
-        } else {
-            Err("Note not found.".to_string())
+            None => {
+                changed.remove(&key).ok_or("Property not found.")?;
+            }
+        }
+
+        let delta = properties::properties_bytes(&changed) as i64 - properties::properties_bytes(&current) as i64;
+        quota::check_quota(quota::usage_of(&notes, owner), quota_for(owner), UsageDelta::resize(delta))?;
+        let audited = serde_json::to_vec(&changed).map_err(|e| format!("Failed to serialize properties: {}", e))?;
+        record_audit(owner, AuditAction::Update, id, now, Some(&audited));
+        let note = notes.get_mut(&id).expect("note exists");
+        note.properties = (!changed.is_empty()).then_some(changed);
+        note.updated_at = Some(now);
+        Ok(())
+    })
+}
+
+/// Replaces `owner`'s property schema, or removes it with `None`. A schema is
+/// refused while any of their notes has a property it would not allow.
+fn set_property_schema_as(owner: Principal, schema: Option<PropertySchema>) -> Result<(), String> {
+    let Some(schema) = schema.filter(|schema| !schema.is_empty()) else {
+        PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow_mut().remove(&owner));
+        return Ok(());
+    };
+    properties::validate_schema(&schema)?;
+    NOTES.with(|notes_cell| {
+        notes_cell
+            .borrow()
+            .values()
+            .filter(|note| note.owner == owner)
+            .flat_map(|note| note.properties.iter().flatten().map(move |property| (note.id, property)))
+            .try_for_each(|(id, (key, value))| {
+                properties::validate_property(key, value, Some(&schema))
+                    .map_err(|e| format!("Note {} does not fit the schema: {}", id, e))
+            })
+    })?;
+    PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow_mut().insert(owner, schema));
+    Ok(())
+}
+
+/// `owner`'s notes whose property `key` meets `condition`, sorted by that
+/// property's value and then by id.
+fn notes_by_property_of(owner: Principal, key: &str, condition: &PropertyCondition, descending: bool) -> Vec<Note> {
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let mut matching: Vec<(&PropertyValue, &Note)> = notes
+            .values()
+            .filter(|note| note.owner == owner)
+            .filter_map(|note| note.properties.as_ref()?.get(key).map(|value| (value, note)))
+            .filter(|(value, _)| condition.matches(value))
+            .collect();
+        matching.sort_by(|(a, a_note), (b, b_note)| {
+            let by_value = properties::order(a, b);
+            if descending { by_value.reverse() } else { by_value }.then(a_note.id.cmp(&b_note.id))
+        });
+        matching.into_iter().map(|(_, note)| hydrate(note)).collect()
+    })
+}
+
+/// One page of the notes `viewer` can list (their own and encrypted notes
+/// shared with them) that match `query`.
+fn query_notes_as(viewer: Principal, query: NoteQuery) -> Result<NoteQueryPage, String> {
+    if let Some(filter) = &query.filter {
+        filter.validate()?;
+    }
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let mut matching: Vec<&Note> = NOTE_STATES.with(|states_cell| {
+            let states = states_cell.borrow();
+            notes
+                .values()
+                .filter(|note| note.owner == viewer || (note.encrypted.is_some() && can_read(note, viewer)))
+                .filter(|note| {
+                    let candidate = Candidate::new(note, states.get(viewer, note.id), stored_content);
+                    query.filter.as_ref().is_none_or(|filter| filter.matches(&candidate))
+                })
+                .collect()
+        });
+        query::sort_notes(&mut matching, query.sort.as_ref());
+        let (start, end, next_cursor) = query::page_bounds(matching.len(), query.cursor, query.limit);
+        Ok(NoteQueryPage {
+            notes: matching[start..end].iter().map(|note| hydrate(note)).collect(),
+            next_cursor,
+            total: matching.len() as u64,
+        })
+    })
+}
+
+/// Unfinished checklist items assigned to `principal`, or unassigned in their
+/// own checklists, soonest due first.
+fn open_tasks_of(principal: Principal) -> Vec<OpenTask> {
+    let mut tasks: Vec<OpenTask> = NOTES.with(|notes_cell| {
+        notes_cell
+            .borrow()
+            .values()
+            .flat_map(|note| {
+                note.checklist.iter().flat_map(|checklist| &checklist.items).filter_map(move |item| {
+                    let mine = item.assignee == Some(principal) || (item.assignee.is_none() && note.owner == principal);
+                    (mine && !item.done).then(|| OpenTask {
+                        note_id: note.id,
+                        note_title: note.title.clone(),
+                        item: item.clone(),
+                    })
+                })
+            })
+            .collect()
+    });
+    checklist::sort_open_tasks(&mut tasks);
+    tasks
+}
+
+/// Sets or clears (`None`) the reminder on one of the caller's notes.
+fn set_reminder_as(caller_principal: Principal, now: u64, id: u64, remind_at: Option<u64>) -> Result<(), String> {
+    if remind_at.is_some_and(|at| at <= now) {
+        return Err("Reminder time must be in the future.".to_string());
+    }
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        let note = notes.get_mut(&id).ok_or_else(|| "Note not found.".to_string())?;
+        if note.owner != caller_principal {
+            return Err("You can only set reminders on your own notes.".to_string());
         }
+        REMINDER_INDEX.with(|index_cell| {
+            let mut index = index_cell.borrow_mut();
+            if let Some(previous) = note.remind_at {
+                index.remove(&(previous, id));
+            }
+            if let Some(at) = remind_at {
+                index.insert((at, id));
+            }
+        });
+        note.remind_at = remind_at;
+        Ok(())
+    })
+}
+
+/// Owner of note `id` if `reader` may see it, and so comment on it.
+fn readable_note_owner(reader: Principal, id: u64) -> Result<Principal, String> {
+    NOTES.with(|notes_cell| match notes_cell.borrow().get(&id) {
+        Some(note) if can_read(note, reader) => Ok(note.owner),
+        _ => Err("Note not found.".to_string()),
     })
 }
 
-// --- Public Query Calls ---
+/// Posts a comment and notifies the note's owner and the author of the
+/// comment replied to, unless they wrote it themselves.
+fn add_comment_as(author: Principal, now: u64, note_id: u64, parent_id: Option<u64>, body: String) -> Result<u64, String> {
+    let owner = readable_note_owner(author, note_id)?;
+    let (comment_id, parent_author) = COMMENTS.with(|comments_cell| {
+        let mut comments = comments_cell.borrow_mut();
+        let comment_id = comments.add(note_id, author, parent_id, &body, now)?;
+        let parent_author = parent_id.and_then(|parent_id| comments.get(parent_id)).map(|parent| parent.author);
+        Ok::<_, String>((comment_id, parent_author))
+    })?;
+    let mut recipients: BTreeSet<Principal> = [owner].into_iter().chain(parent_author).collect();
+    recipients.remove(&author);
+    INBOXES.with(|inboxes_cell| {
+        let mut inboxes = inboxes_cell.borrow_mut();
+        for recipient in recipients {
+            inboxes.push(recipient, now, NotificationKind::Comment { note_id, comment_id, by: author });
+        }
+    });
+    Ok(comment_id)
+}
+
+/// Runs `change` on one of `author`'s comments while they can still read its note.
+fn change_comment_as(
+    author: Principal,
+    comment_id: u64,
+    change: impl FnOnce(&mut Comments) -> Result<(), String>,
+) -> Result<(), String> {
+    let note_id = COMMENTS.with(|comments_cell| comments_cell.borrow().get(comment_id).map(|comment| comment.note_id));
+    readable_note_owner(author, note_id.ok_or("Comment not found.")?)?;
+    COMMENTS.with(|comments_cell| change(&mut comments_cell.borrow_mut()))
+}
+
+/// Moves every reminder due by `now` into its owner's inbox.
+fn deliver_due_reminders(now: u64) {
+    let due: Vec<(u64, u64)> = REMINDER_INDEX.with(|index_cell| {
+        let mut index = index_cell.borrow_mut();
+        let pending = index.split_off(&(now.saturating_add(1), 0));
+        std::mem::replace(&mut *index, pending).into_iter().collect()
+    });
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        for (remind_at, id) in due {
+            // Entries of deleted notes are dropped here rather than on every delete path
+            let Some(note) = notes.get_mut(&id).filter(|note| note.remind_at == Some(remind_at)) else {
+                continue;
+            };
+            note.remind_at = None;
+            let kind = NotificationKind::ReminderDue { note_id: id, due_at: remind_at };
+            INBOXES.with(|inboxes_cell| inboxes_cell.borrow_mut().push(note.owner, now, kind));
+        }
+    });
+}
+
+fn rebuild_reminder_index() {
+    let index: BTreeSet<(u64, u64)> = NOTES.with(|notes_cell| {
+        notes_cell
+            .borrow()
+            .values()
+            .filter_map(|note| note.remind_at.map(|remind_at| (remind_at, note.id)))
+            .collect()
+    });
+    REMINDER_INDEX.with(|index_cell| *index_cell.borrow_mut() = index);
+}
+
+/// Arms a timer for the earliest pending reminder, replacing any earlier one.
+fn schedule_reminder_timer() {
+    if let Some(timer) = REMINDER_TIMER.with(|timer_cell| timer_cell.borrow_mut().take()) {
+        ic_cdk_timers::clear_timer(timer);
+    }
+    let Some((next_at, _)) = REMINDER_INDEX.with(|index_cell| index_cell.borrow().first().copied()) else {
+        return;
+    };
+    let delay = Duration::from_nanos(next_at.saturating_sub(time()));
+    let timer = ic_cdk_timers::set_timer(delay, || {
+        REMINDER_TIMER.with(|timer_cell| *timer_cell.borrow_mut() = None);
+        deliver_due_reminders(time());
+        schedule_reminder_timer();
+    });
+    REMINDER_TIMER.with(|timer_cell| *timer_cell.borrow_mut() = Some(timer));
+}
+
+/// Stores an already validated draft, defaulting its creation time to `now`.
+fn insert_note(owner: Principal, now: u64, draft: NoteDraft) -> u64 {
+    store_new_note(owner, now, draft, None)
+}
+
+fn store_new_note(owner: Principal, now: u64, draft: NoteDraft, encrypted: Option<EncryptedContent>) -> u64 {
+    let new_id = get_next_id();
+    let expires_at = ephemeral_expiry(owner, now);
+
+    let audited_bytes = match &encrypted {
+        Some(encrypted) => encrypted.ciphertext.as_slice(),
+        None => draft.content.as_bytes(),
+    };
+    record_audit(owner, AuditAction::Create, new_id, now, Some(audited_bytes));
+    let content_hash = encrypted.is_none().then(|| intern_content(&draft.content));
+    let indexed_content = encrypted.is_none().then(|| draft.content.clone());
+    let note = Note {
+        id: new_id,
+        owner,
+        title: draft.title,
+        content: String::new(),
+        created_at: draft.created_at.unwrap_or(now),
+        tags: draft.tags,
+        updated_at: draft.updated_at,
+        expires_at,
+        encrypted,
+        content_hash,
+        remind_at: None,
+        checklist: None,
+        properties: None,
+    };
+
+    NOTES.with(|notes_cell| {
+        notes_cell.borrow_mut().insert(new_id, note);
+    });
+    if let Some(expires_at) = expires_at {
+        EXPIRY_INDEX.with(|index_cell| index_cell.borrow_mut().insert((expires_at, new_id)));
+    }
+    if let Some(content) = indexed_content {
+        index_content(owner, now, new_id, &content);
+    }
+
+    new_id
+}
+
+/// Validates a parsed import and stores it, keeping its original timestamp.
+fn import_draft(owner: Principal, now: u64, mut draft: NoteDraft) -> Result<u64, String> {
+    (draft.title, draft.content) = normalize_note(&draft.title, &draft.content)?;
+    draft.tags = normalize_tags(draft.tags)?;
+    let created_at = draft.created_at.unwrap_or(now);
+    if created_at > now {
+        return Err("created_at cannot be in the future.".to_string());
+    }
+    if let Some(updated_at) = draft.updated_at {
+        if updated_at < created_at || updated_at > now {
+            return Err("updated_at must be between created_at and now.".to_string());
+        }
+    }
+    authorize_write(owner, 1)?;
+    authorize_usage(owner, UsageDelta::create(quota::content_bytes(&draft.title, &draft.content, &draft.tags)))?;
+    Ok(insert_note(owner, now, draft))
+}
+
+/// Imports `items` for `owner`, spending one create token per item up front.
+fn import_items_as(owner: Principal, now: u64, items: Vec<ImportItem>) -> Result<Vec<Result<u64, String>>, String> {
+    if items.len() > MAX_IMPORT_BATCH {
+        return Err(format!("Cannot import more than {} notes per call.", MAX_IMPORT_BATCH));
+    }
+    if !items.is_empty() {
+        consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, items.len() as u32)])?;
+    }
+    Ok(items
+        .iter()
+        .map(|item| import::parse_item(item).and_then(|draft| import_draft(owner, now, draft)))
+        .collect())
+}
+
+/// Imports up to `limit` queued notes from one of `owner`'s import jobs.
+fn run_import_job(owner: Principal, now: u64, job_id: u64, limit: usize) -> Result<ImportBatchReport, String> {
+    let mut job = IMPORT_JOBS.with(|jobs_cell| {
+        let mut jobs = jobs_cell.borrow_mut();
+        match jobs.get(&job_id) {
+            Some(job) if job.owner != owner => Err("You can only run your own import jobs.".to_string()),
+            Some(_) => Ok(jobs.remove(&job_id).expect("job exists")),
+            None => Err("Import job not found.".to_string()),
+        }
+    })?;
+    job.touched_at = now;
+
+    if job.pending.is_none() {
+        // A file that fails to parse ends the job; the client must start over.
+        let drafts = job.parse_upload()?;
+        let other_open_bytes = IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow().values().map(ImportJob::held_bytes).sum());
+        // Over the memory limit the upload is kept, so the job can run later.
+        if let Err(e) = job.queue_drafts(drafts, other_open_bytes) {
+            IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(job_id, job));
+            return Err(e);
+        }
+    }
+    let pending = job.pending.get_or_insert_with(Default::default);
+    // Like batches, every note about to be imported spends a create token. A
+    // run imports only as many notes as there are tokens; with none left, it
+    // asks for one so the error says when to retry.
+    let tokens = rate_limit_tokens(owner, now, RateLimitedMethod::Create).max(1) as usize;
+    let count = limit.min(pending.len()).min(tokens);
+    if count > 0 {
+        if let Err(e) = consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, count as u32)]) {
+            IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(job_id, job));
+            return Err(e.into());
+        }
+    }
+    let mut results = Vec::new();
+    while results.len() < count {
+        let Some(draft) = pending.pop_front() else {
+            break;
+        };
+        let title = draft.title.clone();
+        results.push(ImportItemReport {
+            title,
+            result: import_draft(owner, now, draft),
+        });
+    }
+    let remaining = pending.len() as u64;
+
+    if remaining > 0 {
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(job_id, job));
+    }
+    Ok(ImportBatchReport { results, remaining })
+}
+
+// --- Versioned Storage for Safe Migrations ---
+#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
+struct StorageV1 {
+    notes: HashMap<u64, NoteV1>,
+    next_id: u64,
+    version: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, serde::Serialize)]
+struct StorageV2 {
+    notes: NoteStore,
+    next_id: u64,
+    version: u32,
+    config: Option<ConfigArgs>,
+    quota_overrides: Option<HashMap<Principal, Quota>>,
+    admins: Option<BTreeSet<Principal>>,
+    suspended: Option<HashMap<Principal, Suspension>>,
+    admin_log: Option<Vec<AdminLogEntry>>,
+    audit_log: Option<Vec<AuditEntry>>, // only in states saved before the log moved to stable memory
+    blobs: Option<BlobStore>,
+    inboxes: Option<Inboxes>,
+    comments: Option<Comments>,
+    mentions: Option<MentionIndex>,
+    links: Option<LinkIndex>,
+    templates: Option<Templates>,
+    note_states: Option<NoteStates>,
+    property_schemas: Option<BTreeMap<Principal, PropertySchema>>,
+    removed_note_owners: Option<BTreeMap<u64, Principal>>,
+}
+
+impl From<StorageV1> for StorageV2 {
+    fn from(storage: StorageV1) -> Self {
+        StorageV2 {
+            notes: storage.notes.into_iter().map(|(id, note)| (id, note.into())).collect(),
+            next_id: storage.next_id,
+            version: 2,
+            config: None,
+            quota_overrides: None,
+            admins: None,
+            suspended: None,
+            admin_log: None,
+            audit_log: None,
+            blobs: None,
+            inboxes: None,
+            comments: None,
+            mentions: None,
+            links: None,
+            templates: None,
+            note_states: None,
+            property_schemas: None,
+            removed_note_owners: None,
+        }
+    }
+}
+
+// --- Canister Lifecycle Hooks for Stable Storage ---
+#[init]
+fn init(args: Option<ConfigArgs>) {
+    let args = args.unwrap_or_default();
+    if let Err(e) = args.validate() {
+        ic_cdk::trap(&e);
+    }
+    ADMINS.with(|admins_cell| admins_cell.borrow_mut().extend(args.admins.iter().flatten().copied()));
+    CONFIG.with(|config_cell| config_cell.borrow_mut().apply(args));
+    start_expiry_sweeper();
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    // Use defensive programming to prevent upgrade failures
+    let save_result = std::panic::catch_unwind(|| {
+        let combined_state = StorageV2 {
+            notes: NOTES.with(|notes_cell| notes_cell.borrow().clone()),
+            next_id: NEXT_ID.with(|next_id_cell| *next_id_cell.borrow()),
+            version: 2,
+            config: Some(CONFIG.with(|config_cell| ConfigArgs::from(&*config_cell.borrow()))),
+            quota_overrides: Some(QUOTA_OVERRIDES.with(|overrides_cell| overrides_cell.borrow().clone())),
+            admins: Some(ADMINS.with(|admins_cell| admins_cell.borrow().clone())),
+            suspended: Some(SUSPENDED.with(|suspended_cell| suspended_cell.borrow().clone())),
+            admin_log: Some(ADMIN_LOG.with(|log_cell| log_cell.borrow().clone())),
+            audit_log: None,
+            blobs: Some(BLOBS.with(|blobs_cell| blobs_cell.borrow().clone())),
+            inboxes: Some(INBOXES.with(|inboxes_cell| inboxes_cell.borrow().clone())),
+            comments: Some(COMMENTS.with(|comments_cell| comments_cell.borrow().clone())),
+            mentions: Some(MENTIONS.with(|mentions_cell| mentions_cell.borrow().clone())),
+            links: Some(LINKS.with(|links_cell| links_cell.borrow().clone())),
+            templates: Some(TEMPLATES.with(|templates_cell| templates_cell.borrow().clone())),
+            note_states: Some(NOTE_STATES.with(|states_cell| states_cell.borrow().clone())),
+            property_schemas: Some(PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow().clone())),
+            removed_note_owners: Some(REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow().clone())),
+        };
+
+        // Saved after the last audit record so the log is not copied
+        let offset = AUDIT_LOG.with(|log_cell| log_cell.borrow().end_offset());
+        let mut writer = StableWriter::with_memory(CanisterStableMemory::default(), offset);
+        candid::write_args(&mut writer, (combined_state,))?;
+        AUDIT_LOG.with(|log_cell| log_cell.borrow_mut().set_saved_state(offset, writer.offset() - offset));
+        Ok::<(), candid::Error>(())
+    });
+
+    match save_result {
+        Ok(Ok(())) => {
+            ic_cdk::print("Successfully saved state to stable storage");
+        },
+        Ok(Err(e)) => {
+            ic_cdk::print(format!("Warning: Failed to save state to stable storage: {:?}. Upgrade will proceed with empty state.", e));
+        },
+        Err(_) => {
+            ic_cdk::print("Warning: Panic during save to stable storage. Upgrade will proceed with empty state.");
+        }
+    }
+}
+
+#[post_upgrade]
+fn post_upgrade(args: Option<ConfigArgs>) {
+    // Reject bad arguments before touching state; trapping aborts the upgrade
+    let args = args.unwrap_or_default();
+    if let Err(e) = args.validate() {
+        ic_cdk::trap(&e);
+    }
+
+    // Initialize with empty state first to ensure we have a valid state
+    NOTES.with(|notes_cell| {
+        *notes_cell.borrow_mut() = HashMap::new();
+    });
+    NEXT_ID.with(|next_id_cell| {
+        *next_id_cell.borrow_mut() = 1;
+    });
+
+    // States saved since the audit log moved to stable memory sit after it;
+    // older ones are a candid blob at the start of stable memory.
+    let has_stable_log = AUDIT_LOG.with(|log_cell| log_cell.borrow_mut().load());
+    let saved_region = if has_stable_log {
+        AUDIT_LOG.with(|log_cell| log_cell.borrow_mut().take_saved_state())
+    } else {
+        None
+    };
+
+    // Use defensive programming to handle any possible corruption or format incompatibility
+    let restore_result = std::panic::catch_unwind(|| {
+        if has_stable_log {
+            let (offset, len) = saved_region?;
+            let mut bytes = vec![0; len as usize];
+            ic_cdk::api::stable::stable_read(offset, &mut bytes);
+            return candid::decode_one::<StorageV2>(&bytes).ok().filter(|storage| storage.version == 2);
+        }
+
+        // Try to restore the current versioned format first
+        if let Ok((storage_v2,)) = storage::stable_restore::<(StorageV2,)>() {
+            if storage_v2.version == 2 {
+                return Some(storage_v2);
+            }
+        }
+
+        // Notes saved before tags existed
+        if let Ok((storage_v1,)) = storage::stable_restore::<(StorageV1,)>() {
+            if storage_v1.version == 1 {
+                return Some(storage_v1.into());
+            }
+        }
+
+        // Try to restore old format (tuple) as fallback
+        if let Ok((restored_notes, restored_next_id)) = storage::stable_restore::<(HashMap<u64, NoteV1>, u64)>() {
+            return Some(
+                StorageV1 {
+                    notes: restored_notes,
+                    next_id: restored_next_id,
+                    version: 1,
+                }
+                .into(),
+            );
+        }
+
+        None
+    });
+
+    match restore_result {
+        Ok(Some(restored)) => {
+            NOTES.with(|notes_cell| {
+                *notes_cell.borrow_mut() = restored.notes;
+            });
+            NEXT_ID.with(|next_id_cell| {
+                *next_id_cell.borrow_mut() = restored.next_id;
+            });
+            CONFIG.with(|config_cell| {
+                let mut config = CanisterConfig::default();
+                config.apply(restored.config.unwrap_or_default());
+                *config_cell.borrow_mut() = config;
+            });
+            QUOTA_OVERRIDES.with(|overrides_cell| {
+                *overrides_cell.borrow_mut() = restored.quota_overrides.unwrap_or_default();
+            });
+            ADMINS.with(|admins_cell| *admins_cell.borrow_mut() = restored.admins.unwrap_or_default());
+            SUSPENDED.with(|suspended_cell| *suspended_cell.borrow_mut() = restored.suspended.unwrap_or_default());
+            ADMIN_LOG.with(|log_cell| *log_cell.borrow_mut() = restored.admin_log.unwrap_or_default());
+            if !has_stable_log {
+                // Move a log saved on the heap into stable memory, over the blob it came from
+                let entries = restored.audit_log.unwrap_or_default();
+                AUDIT_LOG.with(|log_cell| log_cell.borrow_mut().replace_all(&entries));
+            }
+            BLOBS.with(|blobs_cell| *blobs_cell.borrow_mut() = restored.blobs.unwrap_or_default());
+            INBOXES.with(|inboxes_cell| *inboxes_cell.borrow_mut() = restored.inboxes.unwrap_or_default());
+            COMMENTS.with(|comments_cell| *comments_cell.borrow_mut() = restored.comments.unwrap_or_default());
+            MENTIONS.with(|mentions_cell| *mentions_cell.borrow_mut() = restored.mentions.unwrap_or_default());
+            LINKS.with(|links_cell| *links_cell.borrow_mut() = restored.links.unwrap_or_default());
+            TEMPLATES.with(|templates_cell| *templates_cell.borrow_mut() = restored.templates.unwrap_or_default());
+            NOTE_STATES.with(|states_cell| *states_cell.borrow_mut() = restored.note_states.unwrap_or_default());
+            PROPERTY_SCHEMAS.with(|schemas_cell| *schemas_cell.borrow_mut() = restored.property_schemas.unwrap_or_default());
+            match restored.removed_note_owners {
+                Some(owners) => REMOVED_NOTE_OWNERS.with(|owners_cell| *owners_cell.borrow_mut() = owners),
+                None => rebuild_removed_note_owners(),
+            }
+            intern_inline_contents();
+            ic_cdk::print(format!("Successfully restored {} notes after upgrade", 
+                NOTES.with(|notes_cell| notes_cell.borrow().len())));
+        },
+        Ok(None) => {
+            ic_cdk::print("No valid state found in stable storage, starting with empty state");
+        },
+        Err(_) => {
+            ic_cdk::print("Error during state restoration, starting with empty state");
+        }
+    }
+
+    if !has_stable_log && AUDIT_LOG.with(|log_cell| log_cell.borrow().len()) == 0 {
+        // Nothing was restored; claim stable memory for the log anyway
+        AUDIT_LOG.with(|log_cell| log_cell.borrow_mut().replace_all(&[]));
+    }
+
+    // Upgrade arguments override the restored configuration
+    ADMINS.with(|admins_cell| admins_cell.borrow_mut().extend(args.admins.iter().flatten().copied()));
+    CONFIG.with(|config_cell| config_cell.borrow_mut().apply(args));
+    rebuild_expiry_index();
+    start_expiry_sweeper();
+    rebuild_reminder_index();
+    schedule_reminder_timer();
+}
+
+// --- Public Update Calls ---
+
+#[update]
+fn create_note(title: String, content: String) -> Result<u64, WriteError> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, 1)])?;
+    // Anonymous writes are governed by the configured AnonymousPolicy
+    authorize_write(owner, 1)?;
+
+    let (title, content) = normalize_note(&title, &content)?;
+    authorize_usage(owner, UsageDelta::create(quota::content_bytes(&title, &content, &[])))?;
+
+    Ok(insert_note(owner, now, NoteDraft::new(title, content)))
+}
+
+#[update]
+fn update_note(id: u64, title: String, content: String) -> Result<(), WriteError> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    Ok(update_note_as(caller_principal, now, id, title, content)?)
+}
+
+/// Pins or unpins a note the caller can read, for the caller only.
+#[update]
+fn pin_note(id: u64, pinned: bool) -> Result<(), String> {
+    let viewer = caller();
+    let now = time();
+    consume_rate_limit(viewer, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(viewer, 0)?;
+    update_note_state_as(viewer, id, |state| state.pinned = pinned)
+}
+
+#[update]
+fn favorite_note(id: u64, favorite: bool) -> Result<(), String> {
+    let viewer = caller();
+    let now = time();
+    consume_rate_limit(viewer, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(viewer, 0)?;
+    update_note_state_as(viewer, id, |state| state.favorite = favorite)
+}
+
+/// Archived notes are left out of `get_notes` and `get_shared_encrypted_notes`
+/// for the caller and listed by `get_archived_notes` instead.
+#[update]
+fn archive_note(id: u64, archived: bool) -> Result<(), String> {
+    let viewer = caller();
+    let now = time();
+    consume_rate_limit(viewer, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(viewer, 0)?;
+    update_note_state_as(viewer, id, |state| state.archived = archived)
+}
+
+#[update]
+fn set_note_color(id: u64, color: Option<NoteColor>) -> Result<(), String> {
+    let viewer = caller();
+    let now = time();
+    consume_rate_limit(viewer, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(viewer, 0)?;
+    update_note_state_as(viewer, id, |state| state.color = color)
+}
+
+/// Saves a template. Its title and content may use `{{date}}`, `{{time}}`,
+/// `{{datetime}}`, `{{author}}` and any caller-defined `{{name}}` placeholder.
+#[update]
+fn create_template(input: TemplateInput) -> Result<u64, String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, 1)])?;
+    authorize_write(owner, 0)?;
+    let input = validate_template(input)?;
+    TEMPLATES.with(|templates_cell| templates_cell.borrow_mut().insert(owner, now, input))
+}
+
+#[update]
+fn update_template(id: u64, input: TemplateInput) -> Result<(), String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(owner, 0)?;
+    let input = validate_template(input)?;
+    TEMPLATES.with(|templates_cell| templates_cell.borrow_mut().replace(owner, id, now, input))
+}
+
+#[update]
+fn delete_template(id: u64) -> Result<(), String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Delete, 1)])?;
+    authorize_write(owner, 0)?;
+    TEMPLATES.with(|templates_cell| templates_cell.borrow_mut().remove(owner, id))
+}
+
+/// Creates a note from one of the caller's templates, filling in the built-in
+/// placeholders and `variables`. Placeholders without a value are kept as written.
+#[update]
+fn create_note_from_template(template_id: u64, variables: Vec<TemplateVariable>) -> Result<u64, String> {
+    let author = caller();
+    let now = time();
+    consume_rate_limit(author, now, &[(RateLimitedMethod::Create, 1)])?;
+    authorize_write(author, 1)?;
+    create_note_from_template_as(author, now, template_id, variables)
+}
+
+/// Creates a checklist note. `description` is stored as the note's content.
+#[update]
+fn create_checklist_note(title: String, description: String, items: Vec<NewChecklistItem>) -> Result<u64, String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, 1)])?;
+    authorize_write(owner, 1)?;
+    create_checklist_note_as(owner, now, title, description, items)
+}
+
+#[update]
+fn add_checklist_item(note_id: u64, item: NewChecklistItem) -> Result<u64, String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    change_checklist(caller_principal, now, note_id, None, |checklist| checklist.add_item(item))
+}
+
+/// Flips an item between done and not done and returns its new state. The
+/// item's assignee may toggle it as well as the note's owner.
+#[update]
+fn toggle_checklist_item(note_id: u64, item_id: u64) -> Result<bool, String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    change_checklist(caller_principal, now, note_id, Some(item_id), |checklist| {
+        let item = checklist.item_mut(item_id)?;
+        item.done = !item.done;
+        Ok(item.done)
+    })
+}
+
+/// Reorders a checklist; `order` must list every item id exactly once.
+#[update]
+fn reorder_checklist_items(note_id: u64, order: Vec<u64>) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    change_checklist(caller_principal, now, note_id, None, |checklist| checklist.reorder(&order))
+}
+
+#[update]
+fn remove_checklist_item(note_id: u64, item_id: u64) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    change_checklist(caller_principal, now, note_id, None, |checklist| checklist.remove_item(item_id))
+}
+
+/// Sets one typed property on one of the caller's notes, or removes it with
+/// `None`. If the caller declared a property schema, the value must match it.
+#[update]
+fn set_note_property(note_id: u64, key: String, value: Option<PropertyValue>) -> Result<(), String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(owner, 0)?;
+    set_note_property_as(owner, now, note_id, key, value)
+}
+
+/// Declares the type of each property key the caller's notes may use, or
+/// lifts the restriction with `None`.
+#[update]
+fn set_property_schema(schema: Option<PropertySchema>) -> Result<(), String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(owner, 0)?;
+    set_property_schema_as(owner, schema)
+}
+
+/// Schedules a reminder for one of the caller's notes at `remind_at`
+/// (nanoseconds since the epoch), or clears it with `None`. When it comes due
+/// a `ReminderDue` notification is added to the caller's inbox.
+#[update]
+fn set_reminder(id: u64, remind_at: Option<u64>) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    set_reminder_as(caller_principal, now, id, remind_at)?;
+    schedule_reminder_timer();
+    Ok(())
+}
+
+/// Stores a note encrypted on the client. The canister never sees the
+/// plaintext, so only sizes and recipients are checked.
+#[update]
+fn create_encrypted_note(encrypted: EncryptedContent) -> Result<u64, String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Create, 1)])?;
+    authorize_write(owner, 1)?;
+    create_encrypted_note_as(owner, now, encrypted)
+}
+
+/// Replaces the ciphertext and wrapped keys of an encrypted note.
+#[update]
+fn update_encrypted_note(id: u64, encrypted: EncryptedContent) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    update_encrypted_note_as(caller_principal, now, id, encrypted)
+}
+
+/// Grants `key.recipient` access by adding the note's content key re-wrapped
+/// for them, or replaces the key they already have.
+#[update]
+fn share_encrypted_note(id: u64, key: WrappedKey) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    share_encrypted_note_as(caller_principal, now, id, key)
+}
+
+/// Comments on a note the caller can read, optionally as a reply to `parent_id`.
+#[update]
+fn add_comment(note_id: u64, parent_id: Option<u64>, body: String) -> Result<u64, String> {
+    let author = caller();
+    let now = time();
+    consume_rate_limit(author, now, &[(RateLimitedMethod::Create, 1)])?;
+    authorize_write(author, 0)?;
+    add_comment_as(author, now, note_id, parent_id, body)
+}
+
+#[update]
+fn edit_comment(comment_id: u64, body: String) -> Result<(), String> {
+    let author = caller();
+    let now = time();
+    consume_rate_limit(author, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(author, 0)?;
+    change_comment_as(author, comment_id, |comments| comments.edit(comment_id, author, &body, now))
+}
+
+/// Deletes one of the caller's comments. Replies to it stay in place.
+#[update]
+fn delete_comment(comment_id: u64) -> Result<(), String> {
+    let author = caller();
+    let now = time();
+    consume_rate_limit(author, now, &[(RateLimitedMethod::Delete, 1)])?;
+    authorize_write(author, 0)?;
+    change_comment_as(author, comment_id, |comments| comments.delete(comment_id, author, now))
+}
+
+/// Marks the given notifications as read and returns how many were unread.
+#[update]
+fn mark_read(ids: Vec<u64>) -> u64 {
+    let recipient = caller();
+    INBOXES.with(|inboxes_cell| inboxes_cell.borrow_mut().mark_read(recipient, Some(&ids)))
+}
+
+#[update]
+fn mark_all_read() -> u64 {
+    let recipient = caller();
+    INBOXES.with(|inboxes_cell| inboxes_cell.borrow_mut().mark_read(recipient, None))
+}
+
+/// Removes a recipient's wrapped key. Clients should also rotate the content
+/// key, since the recipient may have kept the old one.
+#[update]
+fn unshare_encrypted_note(id: u64, recipient: Principal) -> Result<(), String> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    change_recipients_as(caller_principal, now, id, AuditAction::Unshare, recipient, |encrypted| {
+        if !encrypted.has_recipient(recipient) {
+            return Err("Principal is not a recipient of this note.".to_string());
+        }
+        encrypted.wrapped_keys.retain(|existing| existing.recipient != recipient);
+        Ok(())
+    })
+}
+
+/// Public key for `scope`, used by clients to encrypt to a principal (user
+/// keys) or to verify keys they derived.
+#[update]
+async fn get_encryption_public_key(scope: KeyScope) -> Result<Vec<u8>, String> {
+    keys::scoped_public_key(&configured_deriver(), scope).await
+}
+
+/// Derives the caller's own key, encrypted under `transport_public_key`.
+#[update]
+async fn derive_user_key(transport_public_key: Vec<u8>) -> Result<Vec<u8>, String> {
+    let caller_principal = caller();
+    authorize_key_derivation(caller_principal, time())?;
+    let input = keys::user_key_input(caller_principal);
+    keys::derive_scoped_key(&configured_deriver(), KeyScope::User, &input, &transport_public_key).await
+}
+
+/// Derives the key of an encrypted note for its owner or one of its recipients.
+#[update]
+async fn derive_note_key(note_id: u64, transport_public_key: Vec<u8>) -> Result<Vec<u8>, String> {
+    let caller_principal = caller();
+    authorize_key_derivation(caller_principal, time())?;
+    let input = note_key_input_for(caller_principal, note_id)?;
+    keys::derive_scoped_key(&configured_deriver(), KeyScope::Note, &input, &transport_public_key).await
+}
+
+/// Validates every operation first and then applies all of them, or none.
+/// Creates, updates and deletes follow the same rules, and rate limits, as the
+/// single-note calls.
+#[update]
+fn batch_apply(ops: Vec<BatchOp>) -> Result<Vec<BatchOpResult>, BatchError> {
+    apply_batch(caller(), time(), ops)
+}
+
+/// Imports up to `MAX_IMPORT_BATCH` notes, returning a result per item in order.
+/// Items are validated and rate limited like `create_note`; failed items do
+/// not stop the batch.
+#[update]
+fn import_notes(items: Vec<ImportItem>) -> Result<Vec<Result<u64, String>>, String> {
+    import_items_as(caller(), time(), items)
+}
+
+/// Opens an import job for an Evernote or Notion export. Upload the file with
+/// `upload_import_chunk`, then call `run_import_batch` until nothing remains.
+#[update]
+fn start_import(source: ImportSource) -> Result<u64, String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(owner, 0)?;
+    start_import_as(owner, now, source)
+}
+
+/// Appends bytes to an import job's upload and returns the total uploaded so far.
+#[update]
+fn upload_import_chunk(job_id: u64, chunk: Vec<u8>) -> Result<u64, String> {
+    let owner = caller();
+    let now = time();
+    consume_rate_limit(owner, now, &[(RateLimitedMethod::Update, 1)])?;
+    authorize_write(owner, 0)?;
+    upload_import_chunk_as(owner, now, job_id, chunk)
+}
+
+fn start_import_as(owner: Principal, now: u64, source: ImportSource) -> Result<u64, String> {
+    IMPORT_JOBS.with(|jobs_cell| {
+        let mut jobs = jobs_cell.borrow_mut();
+        if jobs.values().filter(|job| job.owner == owner).count() >= MAX_IMPORT_JOBS_PER_OWNER {
+            return Err(format!(
+                "You already have {} import jobs open. Finish or cancel one first.",
+                MAX_IMPORT_JOBS_PER_OWNER
+            ));
+        }
+        let job_id = NEXT_IMPORT_JOB_ID.with(|next_id_cell| {
+            let id = *next_id_cell.borrow();
+            *next_id_cell.borrow_mut() += 1;
+            id
+        });
+        jobs.insert(job_id, ImportJob::new(owner, source, now));
+        Ok(job_id)
+    })
+}
+
+fn upload_import_chunk_as(owner: Principal, now: u64, job_id: u64, chunk: Vec<u8>) -> Result<u64, String> {
+    IMPORT_JOBS.with(|jobs_cell| {
+        let mut jobs = jobs_cell.borrow_mut();
+        let open_bytes = jobs.values().map(ImportJob::held_bytes).sum();
+        let job = jobs.get_mut(&job_id).ok_or("Import job not found.")?;
+        if job.owner != owner {
+            return Err("You can only upload to your own import jobs.".to_string());
+        }
+        if job.pending.is_some() {
+            return Err("Import job has already started processing.".to_string());
+        }
+        job.check_upload(open_bytes, chunk.len())?;
+        job.upload.extend_from_slice(&chunk);
+        job.touched_at = now;
+        Ok(job.upload.len() as u64)
+    })
+}
+
+/// Imports the next batch of notes from an uploaded export.
+#[update]
+fn run_import_batch(job_id: u64, limit: Option<u32>) -> Result<ImportBatchReport, String> {
+    let limit = limit.map_or(MAX_IMPORT_BATCH, |l| (l as usize).clamp(1, MAX_IMPORT_BATCH));
+    run_import_job(caller(), time(), job_id, limit)
+}
+
+#[update]
+fn cancel_import(job_id: u64) -> Result<(), String> {
+    let owner = caller();
+    IMPORT_JOBS.with(|jobs_cell| {
+        let mut jobs = jobs_cell.borrow_mut();
+        match jobs.get(&job_id) {
+            Some(job) if job.owner == owner => {
+                jobs.remove(&job_id);
+                Ok(())
+            }
+            Some(_) => Err("You can only cancel your own import jobs.".to_string()),
+            None => Err("Import job not found.".to_string()),
+        }
+    })
+}
+
+#[update]
+fn delete_note(id: u64) -> Result<(), WriteError> {
+    let caller_principal = caller();
+    let now = time();
+    consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Delete, 1)])?;
+    authorize_write(caller_principal, 0)?;
+    Ok(delete_note_as(caller_principal, now, id)?)
+}
+
+// --- Admin Calls ---
+// Admins are the controllers plus the principals in ADMINS. Every successful
+// admin action is appended to the admin log.
+
+#[update]
+fn add_admin(principal: Principal) -> Result<(), String> {
+    let admin = require_admin()?;
+    if principal == Principal::anonymous() {
+        return Err("The anonymous principal cannot be an admin.".to_string());
+    }
+    if !ADMINS.with(|admins_cell| admins_cell.borrow_mut().insert(principal)) {
+        return Err("Principal is already an admin.".to_string());
+    }
+    record_admin_action(admin, time(), AdminAction::AddAdmin { principal });
+    Ok(())
+}
+
+/// Controllers stay admins regardless; only explicitly added admins can be removed.
+#[update]
+fn remove_admin(principal: Principal) -> Result<(), String> {
+    let admin = require_admin()?;
+    if !ADMINS.with(|admins_cell| admins_cell.borrow_mut().remove(&principal)) {
+        return Err("Principal is not an added admin.".to_string());
+    }
+    record_admin_action(admin, time(), AdminAction::RemoveAdmin { principal });
+    Ok(())
+}
+
+#[update]
+fn force_delete_note(id: u64, reason: String) -> Result<(), String> {
+    let admin = require_admin()?;
+    force_delete_note_as(admin, time(), id, reason)
+}
+
+/// Blocks `principal` from every write endpoint; their notes stay readable.
+#[update]
+fn suspend_principal(principal: Principal, reason: String) -> Result<(), String> {
+    let admin = require_admin()?;
+    suspend_principal_as(admin, time(), principal, reason)
+}
+
+#[update]
+fn unsuspend_principal(principal: Principal) -> Result<(), String> {
+    let admin = require_admin()?;
+    if SUSPENDED.with(|suspended_cell| suspended_cell.borrow_mut().remove(&principal)).is_none() {
+        return Err("Principal is not suspended.".to_string());
+    }
+    record_admin_action(admin, time(), AdminAction::UnsuspendPrincipal { principal });
+    Ok(())
+}
+
+/// Changes the runtime configuration; omitted fields keep their current value.
+/// Controllers only, since these settings apply to every user.
+#[update]
+fn update_config(args: ConfigArgs) -> Result<(), String> {
+    let controller = caller();
+    if !is_controller(controller) {
+        return Err("Only controllers can change the configuration.".to_string());
+    }
+    if args.admins.is_some() {
+        return Err("Use add_admin and remove_admin to change admins.".to_string());
+    }
+    args.validate()?;
+    CONFIG.with(|config_cell| config_cell.borrow_mut().apply(args.clone()));
+    record_admin_action(controller, time(), AdminAction::UpdateConfig { args });
+    Ok(())
+}
+
+/// Chooses how writes from the anonymous principal are handled.
+#[update]
+fn set_anonymous_policy(policy: AnonymousPolicy) -> Result<(), String> {
+    update_config(ConfigArgs {
+        anonymous_policy: Some(policy),
+        ..ConfigArgs::default()
+    })
+}
+
+/// Sets the quota for principals without an override.
+#[update]
+fn set_default_quota(quota: Quota) -> Result<(), String> {
+    update_config(ConfigArgs {
+        default_quota: Some(quota),
+        ..ConfigArgs::default()
+    })
+}
+
+/// Gives `principal` its own quota, or restores the default with `None`.
+/// Lowering a quota below current usage only blocks further growth.
+#[update]
+fn set_quota_override(principal: Principal, quota: Option<Quota>) -> Result<(), String> {
+    let admin = require_admin()?;
+    QUOTA_OVERRIDES.with(|overrides_cell| {
+        let mut overrides = overrides_cell.borrow_mut();
+        match quota {
+            Some(quota) => overrides.insert(principal, quota),
+            None => overrides.remove(&principal),
+        }
+    });
+    record_admin_action(admin, time(), AdminAction::SetQuotaOverride { principal, quota });
+    Ok(())
+}
+
+fn force_delete_note_as(admin: Principal, now: u64, id: u64, reason: String) -> Result<(), String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required.".to_string());
+    }
+    let note = NOTES.with(|notes_cell| notes_cell.borrow_mut().remove(&id)).ok_or("Note not found.")?;
+    release_note(&note);
+    record_audit(admin, AuditAction::ForceDelete, id, now, None);
+    record_admin_action(admin, now, AdminAction::ForceDeleteNote { id, owner: note.owner, reason });
+    Ok(())
+}
+
+fn suspend_principal_as(admin: Principal, now: u64, principal: Principal, reason: String) -> Result<(), String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required.".to_string());
+    }
+    // Controllers count as admins too, and cannot be removed as one.
+    if is_admin(principal) {
+        return Err("Admins cannot be suspended; remove them as admin first.".to_string());
+    }
+    let suspension = Suspension {
+        reason: reason.clone(),
+        suspended_by: admin,
+        suspended_at: now,
+    };
+    let replaced = SUSPENDED.with(|suspended_cell| suspended_cell.borrow_mut().insert(principal, suspension));
+    record_admin_action(admin, now, AdminAction::SuspendPrincipal { principal, reason, replaced });
+    Ok(())
+}
+
+fn global_stats() -> GlobalStats {
+    GlobalStats {
+        open_import_jobs: IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow().len() as u64),
+        admins: ADMINS.with(|admins_cell| admins_cell.borrow().len() as u64),
+        suspended_principals: SUSPENDED.with(|suspended_cell| suspended_cell.borrow().len() as u64),
+        unique_contents: BLOBS.with(|blobs_cell| blobs_cell.borrow().len() as u64),
+        stored_content_bytes: BLOBS.with(|blobs_cell| blobs_cell.borrow().stored_bytes()),
+        ..NOTES.with(|notes_cell| admin::note_stats(&notes_cell.borrow()))
+    }
+}
+
+fn update_note_as(caller_principal: Principal, now: u64, id: u64, title: String, content: String) -> Result<(), String> {
+    let (title, content) = normalize_note(&title, &content)?;
+
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        if let Some(note) = notes.get(&id) {
+            // Check if the caller owns this note
+            if note.owner != caller_principal {
+                return Err("You can only update your own notes.".to_string());
+            }
+            if note.encrypted.is_some() {
+                return Err("Encrypted notes can only be changed with update_encrypted_note.".to_string());
+            }
+            let delta = UsageDelta::update(note, &title, &content);
+            quota::check_quota(quota::usage_of(&notes, caller_principal), quota_for(caller_principal), delta)?;
+
+            record_audit(caller_principal, AuditAction::Update, id, now, Some(content.as_bytes()));
+            let note = notes.get_mut(&id).expect("note exists");
+            // Intern before releasing so unchanged content keeps its blob
+            let content_hash = intern_content(&content);
+            release_content(note);
+            note.title = title;
+            note.content = String::new();
+            note.content_hash = Some(content_hash);
+            note.updated_at = Some(now);
+            Ok(())
+        } else {
+            Err("Note not found.".to_string())
+        }
+    })?;
+    index_content(caller_principal, now, id, &content);
+    Ok(())
+}
+
+fn configured_deriver() -> ConfiguredDeriver {
+    let derivation = CONFIG.with(|config_cell| config_cell.borrow().key_derivation.clone());
+    ConfiguredDeriver::new(&derivation, ic_cdk::id())
+}
+
+/// Key derivation costs cycles, so it shares the update rate limit. All
+/// anonymous callers share one identity, which makes their keys meaningless.
+fn authorize_key_derivation(caller_principal: Principal, now: u64) -> Result<(), String> {
+    if caller_principal == Principal::anonymous() {
+        return Err("Sign in to derive encryption keys.".to_string());
+    }
+    authorize_write(caller_principal, 0)?;
+    Ok(consume_rate_limit(caller_principal, now, &[(RateLimitedMethod::Update, 1)])?)
+}
+
+fn note_key_input_for(caller_principal: Principal, note_id: u64) -> Result<Vec<u8>, String> {
+    NOTES.with(|notes_cell| match notes_cell.borrow().get(&note_id) {
+        Some(note) if note.encrypted.is_none() => Err("Note is not encrypted.".to_string()),
+        Some(note) if can_read(note, caller_principal) => Ok(keys::note_key_input(note_id)),
+        Some(_) => Err("You are not a recipient of this note.".to_string()),
+        None => Err("Note not found.".to_string()),
+    })
+}
+
+fn create_encrypted_note_as(owner: Principal, now: u64, encrypted: EncryptedContent) -> Result<u64, String> {
+    encryption::validate_encrypted(owner, &encrypted, max_note_size_bytes())?;
+    authorize_usage(owner, UsageDelta::create(encrypted.stored_bytes()))?;
+    Ok(store_new_note(owner, now, NoteDraft::new(String::new(), String::new()), Some(encrypted)))
+}
+
+fn update_encrypted_note_as(caller_principal: Principal, now: u64, id: u64, encrypted: EncryptedContent) -> Result<(), String> {
+    change_encrypted_note(caller_principal, now, id, AuditAction::Update, None, |current| {
+        encryption::validate_encrypted(caller_principal, &encrypted, max_note_size_bytes())?;
+        *current = encrypted;
+        Ok(())
+    })
+}
+
+/// Adds or replaces `key.recipient`'s wrapped key and notifies a new recipient.
+fn share_encrypted_note_as(caller_principal: Principal, now: u64, id: u64, key: WrappedKey) -> Result<(), String> {
+    let recipient = key.recipient;
+    let mut newly_shared = false;
+    change_recipients_as(caller_principal, now, id, AuditAction::Share, recipient, |encrypted| {
+        encryption::validate_wrapped_key(&key)?;
+        newly_shared = !encrypted.has_recipient(recipient);
+        encrypted.wrapped_keys.retain(|existing| existing.recipient != recipient);
+        encrypted.wrapped_keys.push(key);
+        Ok(())
+    })?;
+    if newly_shared && recipient != caller_principal {
+        let kind = NotificationKind::SharedWithYou { note_id: id, by: caller_principal };
+        INBOXES.with(|inboxes_cell| inboxes_cell.borrow_mut().push(recipient, now, kind));
+    }
+    Ok(())
+}
+
+/// Edits only the wrapped keys; the owner must keep their own key. The change
+/// is audited as `action` on `recipient`.
+fn change_recipients_as(
+    caller_principal: Principal,
+    now: u64,
+    id: u64,
+    action: AuditAction,
+    recipient: Principal,
+    change: impl FnOnce(&mut EncryptedContent) -> Result<(), String>,
+) -> Result<(), String> {
+    change_encrypted_note(caller_principal, now, id, action, Some(recipient), |current| {
+        change(current)?;
+        if current.wrapped_keys.len() > encryption::MAX_RECIPIENTS {
+            return Err(format!("An encrypted note can have at most {} recipients.", encryption::MAX_RECIPIENTS));
+        }
+        if !current.has_recipient(caller_principal) {
+            return Err("The owner must have a wrapped key for their own note.".to_string());
+        }
+        Ok(())
+    })
+}
+
+/// Applies `change` to a copy of the caller's encrypted note and stores it if
+/// the change succeeds and the result fits in the caller's quota.
+fn change_encrypted_note(
+    caller_principal: Principal,
+    now: u64,
+    id: u64,
+    action: AuditAction,
+    recipient: Option<Principal>,
+    change: impl FnOnce(&mut EncryptedContent) -> Result<(), String>,
+) -> Result<(), String> {
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        let note = notes.get(&id).ok_or("Note not found.")?;
+        if note.owner != caller_principal {
+            return Err("You can only update your own notes.".to_string());
+        }
+        let mut encrypted = note.encrypted.clone().ok_or("Note is not encrypted.")?;
+        change(&mut encrypted)?;
+
+        let delta = UsageDelta {
+            notes: 0,
+            bytes: encrypted.stored_bytes() as i64 - quota::note_bytes(note) as i64,
+        };
+        quota::check_quota(quota::usage_of(&notes, caller_principal), quota_for(caller_principal), delta)?;
+
+        record_audit_for(caller_principal, action, id, now, recipient, Some(&encrypted.ciphertext));
+        let note = notes.get_mut(&id).expect("note exists");
+        note.encrypted = Some(encrypted);
+        note.updated_at = Some(now);
+        Ok(())
+    })
+}
+
+fn delete_note_as(caller_principal: Principal, now: u64, id: u64) -> Result<(), String> {
+    NOTES.with(|notes_cell| {
+        let mut notes = notes_cell.borrow_mut();
+        if let Some(note) = notes.get(&id) {
+            // Check if the caller owns this note
+            if note.owner != caller_principal {
+                return Err("You can only delete your own notes.".to_string());
+            }
+            if let Some(note) = notes.remove(&id) {
+                release_note(&note);
+            }
+            record_audit(caller_principal, AuditAction::Delete, id, now, None);
+            Ok(())
+        } else {
+            Err("Note not found.".to_string())
+        }
+    })
+}
+
+fn apply_batch(caller_principal: Principal, now: u64, mut ops: Vec<BatchOp>) -> Result<Vec<BatchOpResult>, BatchError> {
+    if ops.len() > MAX_BATCH_OPS {
+        return Err(BatchError::TooManyOps { max: MAX_BATCH_OPS as u32 });
+    }
+
+    let creates = ops.iter().filter(|op| matches!(op, BatchOp::Create { .. })).count();
+    let updates = ops.iter().filter(|op| matches!(op, BatchOp::Update { .. })).count();
+    let deletes = ops.len() - creates - updates;
+    let costs: Vec<(RateLimitedMethod, u32)> = [
+        (RateLimitedMethod::Create, creates),
+        (RateLimitedMethod::Update, updates),
+        (RateLimitedMethod::Delete, deletes),
+    ]
+    .into_iter()
+    .filter(|&(_, count)| count > 0)
+    .map(|(method, count)| (method, count as u32))
+    .collect();
+    consume_rate_limit(caller_principal, now, &costs).map_err(BatchError::RateLimited)?;
+    authorize_write(caller_principal, creates).map_err(BatchError::Rejected)?;
+
+    // Store normalized text; ops that fail normalization are reported by validate_ops
+    for op in ops.iter_mut() {
+        if let BatchOp::Create { title, content } | BatchOp::Update { title, content, .. } = op {
+            if let Ok(normalized) = normalize_note(title, content) {
+                (*title, *content) = normalized;
+            }
+        }
+    }
+    let errors = NOTES.with(|notes_cell| batch::validate_ops(&notes_cell.borrow(), caller_principal, &ops));
+    if !errors.is_empty() {
+        return Err(BatchError::InvalidOps(errors));
+    }
+
+    // Check the quota after each operation so the per-op checks below cannot fail
+    let (mut usage, deltas) = NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        (quota::usage_of(&notes, caller_principal), quota::batch_deltas(&notes, &ops))
+    });
+    let quota = quota_for(caller_principal);
+    for (index, delta) in deltas.into_iter().enumerate() {
+        usage = quota::check_quota(usage, quota, delta).map_err(|message| {
+            BatchError::InvalidOps(vec![BatchOpError {
+                index: index as u32,
+                message,
+            }])
+        })?;
+    }
+
+    // Every operation was checked above, so a failure here is a bug; trapping
+    // rolls back the whole message and keeps the batch atomic.
+    Ok(ops
+        .into_iter()
+        .map(|op| match op {
+            BatchOp::Create { title, content } => {
+                BatchOpResult::Created(insert_note(caller_principal, now, NoteDraft::new(title, content)))
+            }
+            BatchOp::Update { id, title, content } => {
+                update_note_as(caller_principal, now, id, title, content).expect("validated batch update");
+                BatchOpResult::Updated(id)
+            }
+            BatchOp::Delete { id } => {
+                delete_note_as(caller_principal, now, id).expect("validated batch delete");
+                BatchOpResult::Deleted(id)
+            }
+        })
+        .collect())
+}
+
+/// Drops ingress messages from callers whose bucket is already empty, before
+/// they are accepted and paid for. Replicas run this independently and cannot
+/// change state, so the update call itself still enforces the limit.
+#[inspect_message]
+fn inspect_message() {
+    let method = match ic_cdk::api::call::method_name().as_str() {
+        "create_note" => Some(RateLimitedMethod::Create),
+        "update_note" => Some(RateLimitedMethod::Update),
+        "delete_note" => Some(RateLimitedMethod::Delete),
+        _ => None,
+    };
+    if let Some(method) = method {
+        let limits = CONFIG.with(|config_cell| config_cell.borrow().rate_limits);
+        let over_limit = RATE_LIMITER
+            .with(|limiter_cell| limiter_cell.borrow().check(&limits, caller(), time(), &[(method, 1)]))
+            .is_err();
+        if over_limit {
+            return;
+        }
+    }
+    ic_cdk::api::call::accept_message();
+}
+
+// --- Public Query Calls ---
+
+#[query]
+fn get_notes() -> Vec<Note> {
+    let owner = caller();
+    // Allow anonymous users to see their notes for demo purposes
+    NOTES.with(|notes_cell| list_for_viewer(owner, notes_cell.borrow().values().filter(|note| note.owner == owner)))
+}
+
+/// The caller's own notes that they archived, by ascending id.
+#[query]
+fn get_archived_notes() -> Vec<Note> {
+    let owner = caller();
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let mut archived: Vec<Note> = NOTE_STATES.with(|states_cell| {
+            let states = states_cell.borrow();
+            notes
+                .values()
+                .filter(|note| note.owner == owner && states.get(owner, note.id).archived)
+                .map(hydrate)
+                .collect()
+        });
+        archived.sort_by_key(|note| note.id);
+        archived
+    })
+}
+
+/// Searches the caller's notes and the encrypted notes shared with them.
+/// Filters combine date ranges, tags, owner, text, the archive flag and
+/// properties with `And`/`Or`; results are sorted and paged.
+#[query]
+fn query_notes(query: NoteQuery) -> Result<NoteQueryPage, String> {
+    query_notes_as(caller(), query)
+}
+
+/// The caller's property schema, if they declared one.
+#[query]
+fn get_property_schema() -> Option<PropertySchema> {
+    let owner = caller();
+    PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow().get(&owner).cloned())
+}
+
+/// The caller's notes whose property `key` meets `condition`, sorted by its
+/// value (ascending unless `descending`), ties by id. Archived notes are included.
+#[query]
+fn get_notes_by_property(key: String, condition: PropertyCondition, descending: bool) -> Vec<Note> {
+    notes_by_property_of(caller(), &key, &condition, descending)
+}
+
+/// The caller's pins, favorites, archive flags and colors, for every note
+/// where they set any.
+#[query]
+fn get_my_note_states() -> Vec<NoteStateEntry> {
+    let viewer = caller();
+    NOTE_STATES.with(|states_cell| states_cell.borrow().of_viewer(viewer))
+}
+
+#[query]
+fn get_note_by_id(id: u64) -> Option<Note> {
+    NOTES.with(|notes_cell| {
+        notes_cell
+            .borrow()
+            .get(&id)
+            .filter(|note| note.encrypted.is_none() || can_read(note, caller()))
+            .map(hydrate)
+    })
+    // This is intentionally public, anyone can try to fetch a plain note by ID if they know it.
+}
+
+/// Lowest note id per title key among `owner`'s plaintext notes, for `[[Title]]` links.
+fn titles_of(notes: &NoteStore, owner: Principal) -> HashMap<String, u64> {
+    let mut titles: HashMap<String, u64> = HashMap::new();
+    for note in notes.values().filter(|note| note.owner == owner && note.encrypted.is_none()) {
+        let id = titles.entry(links::title_key(&note.title)).or_insert(note.id);
+        *id = (*id).min(note.id);
+    }
+    titles
+}
+
+/// `source`'s links as its owner sees them: id links must point to a note the
+/// owner can read and title links to one of the owner's own notes.
+fn resolve_links(notes: &NoteStore, source: &Note, titles: &HashMap<String, u64>) -> Vec<LinkEdge> {
+    LINKS.with(|links_cell| {
+        links_cell
+            .borrow()
+            .targets(source.id)
+            .iter()
+            .map(|target| LinkEdge {
+                source: source.id,
+                target: target.clone(),
+                resolved: match target {
+                    LinkTarget::Id(id) => notes.get(id).filter(|note| can_read(note, source.owner)).map(|note| note.id),
+                    LinkTarget::Title(title) => titles.get(&links::title_key(title)).copied(),
+                },
+            })
+            .collect()
+    })
+}
+
+fn graph_node(note: &Note) -> GraphNode {
+    GraphNode {
+        id: note.id,
+        title: note.title.clone(),
+    }
+}
+
+fn backlinks_as(reader: Principal, target_id: u64) -> Result<Vec<GraphNode>, String> {
+    readable_note_owner(reader, target_id)?;
+    let sources: Vec<u64> = LINKS.with(|links_cell| links_cell.borrow().sources().collect());
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let mut titles_by_owner: HashMap<Principal, HashMap<String, u64>> = HashMap::new();
+        let mut backlinks: Vec<GraphNode> = sources
+            .iter()
+            .filter_map(|id| notes.get(id))
+            .filter(|source| can_read(source, reader))
+            .filter(|source| {
+                let titles = titles_by_owner
+                    .entry(source.owner)
+                    .or_insert_with(|| titles_of(&notes, source.owner));
+                resolve_links(&notes, source, titles)
+                    .iter()
+                    .any(|edge| edge.resolved == Some(target_id))
+            })
+            .map(graph_node)
+            .collect();
+        backlinks.sort_by_key(|node| node.id);
+        Ok(backlinks)
+    })
+}
+
+fn link_graph_of(owner: Principal) -> LinkGraph {
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let mut owned: Vec<&Note> = notes
+            .values()
+            .filter(|note| note.owner == owner && note.encrypted.is_none())
+            .collect();
+        owned.sort_by_key(|note| note.id);
+        let titles = titles_of(&notes, owner);
+        LinkGraph {
+            nodes: owned.iter().map(|note| graph_node(note)).collect(),
+            edges: owned.iter().flat_map(|note| resolve_links(&notes, note, &titles)).collect(),
+        }
+    })
+}
+
+/// Notes the caller can read that link to `note_id`, by ascending id.
+#[query]
+fn get_backlinks(note_id: u64) -> Result<Vec<GraphNode>, String> {
+    backlinks_as(caller(), note_id)
+}
+
+/// The caller's plaintext notes and the links between them, for a graph view.
+/// Edges with no `resolved` note are broken links.
+#[query]
+fn get_link_graph() -> LinkGraph {
+    link_graph_of(caller())
+}
+
+/// The caller's templates, by ascending id.
+#[query]
+fn list_templates() -> Vec<Template> {
+    let owner = caller();
+    TEMPLATES.with(|templates_cell| templates_cell.borrow().owned_by(owner))
+}
+
+/// Unfinished checklist items assigned to the caller, plus unassigned items in
+/// the caller's own checklists, soonest due first.
+#[query]
+fn get_my_open_tasks() -> Vec<OpenTask> {
+    open_tasks_of(caller())
+}
+
+/// Notes the caller can read whose content mentions them, by ascending id.
+#[query]
+fn get_notes_mentioning_me() -> Vec<Note> {
+    let reader = caller();
+    let ids = MENTIONS.with(|mentions_cell| mentions_cell.borrow().notes_mentioning(reader));
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        ids.iter()
+            .filter_map(|id| notes.get(id))
+            .filter(|note| can_read(note, reader))
+            .map(hydrate)
+            .collect()
+    })
+}
+
+/// Comments on a note the caller can read, oldest first. Replies point to
+/// their parent through `parent_id`.
+#[query]
+fn get_comments(note_id: u64) -> Result<Vec<Comment>, String> {
+    readable_note_owner(caller(), note_id)?;
+    Ok(COMMENTS.with(|comments_cell| comments_cell.borrow().for_note(note_id)))
+}
+
+/// Encrypted notes other principals have shared with the caller.
+#[query]
+fn get_shared_encrypted_notes() -> Vec<Note> {
+    let recipient = caller();
+    NOTES.with(|notes_cell| {
+        let notes = notes_cell.borrow();
+        let shared = notes
+            .values()
+            .filter(|note| note.owner != recipient && note.encrypted.as_ref().is_some_and(|e| e.has_recipient(recipient)));
+        list_for_viewer(recipient, shared)
+    })
+}
+
+#[query]
+fn list_admins() -> Result<Vec<Principal>, String> {
+    require_admin()?;
+    Ok(ADMINS.with(|admins_cell| admins_cell.borrow().iter().copied().collect()))
+}
+
+#[query]
+fn get_global_stats() -> Result<GlobalStats, String> {
+    require_admin()?;
+    Ok(global_stats())
+}
+
+/// Admin actions, newest first.
+#[query]
+fn get_admin_log(cursor: Option<u64>, limit: Option<u32>) -> Result<AdminLogPage, String> {
+    require_admin()?;
+    Ok(ADMIN_LOG.with(|log_cell| admin::log_page(&log_cell.borrow(), cursor, limit)))
+}
+
+/// Audit entries for one note, newest first. Open to admins and the note's owner.
+#[query]
+fn get_note_audit_log(note_id: u64, cursor: Option<u64>, limit: Option<u32>) -> Result<AuditPage, String> {
+    note_audit_log_as(caller(), note_id, cursor, limit)
+}
+
+/// The owner keeps access after the note is removed, to the entries that
+/// record its removal.
+fn note_audit_log_as(caller_principal: Principal, note_id: u64, cursor: Option<u64>, limit: Option<u32>) -> Result<AuditPage, String> {
+    let owner = NOTES
+        .with(|notes_cell| notes_cell.borrow().get(&note_id).map(|note| note.owner))
+        .or_else(|| REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow().get(&note_id).copied()));
+    if owner != Some(caller_principal) && !is_admin(caller_principal) {
+        return Err("Only the note's owner or an admin can view its audit log.".to_string());
+    }
+    Ok(AUDIT_LOG.with(|log_cell| log_cell.borrow().page(|entry| entry.note_id == note_id, cursor, limit)))
+}
+
+/// Audit entries for changes made by `principal`, newest first. Open to admins and the principal.
+#[query]
+fn get_principal_audit_log(principal: Principal, cursor: Option<u64>, limit: Option<u32>) -> Result<AuditPage, String> {
+    let caller_principal = caller();
+    if caller_principal != principal && !is_admin(caller_principal) {
+        return Err("You can only view your own audit log.".to_string());
+    }
+    Ok(AUDIT_LOG.with(|log_cell| log_cell.borrow().page(|entry| entry.caller == principal, cursor, limit)))
+}
+
+/// Recomputes the hashes of up to `limit` audit entries, starting after
+/// `from` or at the beginning of the log. Pass the result back as `from`
+/// until it is `complete` to verify the whole chain.
+#[query]
+fn verify_audit_chain(from: Option<AuditCheckpoint>, limit: Option<u32>) -> Result<AuditChainHead, String> {
+    AUDIT_LOG.with(|log_cell| log_cell.borrow().verify(from, limit))
+}
+
+/// The caller's notifications, newest first, with their unread count.
+#[query]
+fn list_notifications(cursor: Option<u64>, limit: Option<u32>, unread_only: bool) -> NotificationPage {
+    let recipient = caller();
+    INBOXES.with(|inboxes_cell| inboxes_cell.borrow().page(recipient, cursor, limit, unread_only))
+}
 
 #[query]
-fn get_notes() -> Vec<Note> {
+fn get_unread_count() -> u64 {
+    let recipient = caller();
+    INBOXES.with(|inboxes_cell| inboxes_cell.borrow().unread_count(recipient))
+}
+
+/// The configuration currently in effect.
+#[query]
+fn get_config() -> CanisterConfig {
+    CONFIG.with(|config_cell| config_cell.borrow().clone())
+}
+
+/// The caller's note count and bytes stored, with the quota that applies to them.
+#[query]
+fn get_my_usage() -> UsageReport {
     let owner = caller();
-    // Allow anonymous users to see their notes for demo purposes
-    NOTES.with(|notes_cell| {
-        notes_cell
-            .borrow()
-            .values()
-            .filter(|note| note.owner == owner)
-            .cloned()
-            .collect()
-    })
+    UsageReport {
+        usage: NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner)),
+        quota: quota_for(owner),
+    }
 }
 
+/// Fetches up to `MAX_NOTES_BY_IDS` notes in one call; results follow the order of `ids`.
 #[query]
-fn get_note_by_id(id: u64) -> Option<Note> {
-    NOTES.with(|notes_cell| notes_cell.borrow().get(&id).cloned())
-    // This is intentionally public, anyone can try to fetch a note by ID if they know it.
+fn get_notes_by_ids(ids: Vec<u64>) -> Result<Vec<NoteLookup>, String> {
+    lookup_notes(caller(), &ids)
+}
+
+#[query]
+fn export_notes(format: ExportFormat, cursor: Option<u64>, limit: Option<u32>) -> Result<ExportChunk, String> {
+    let owner = caller();
+    NOTES.with(|notes_cell| export::export_chunk(&notes_cell.borrow(), owner, format, cursor, limit))
 }
 
 
@@ -246,6 +2351,10 @@ candid::export_service!();
 mod tests {
     use super::*;
     use candid::Principal; // Corrected Principal import from candid crate
+
+    thread_local! {
+        pub(super) static CONTROLLERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    }
     // use ic_cdk::test; // test module not available in this ic-cdk version for set_caller
 
     // Helper to set the caller for testing purposes - In ic-cdk 0.12.x, direct mocking is hard.
@@ -258,6 +2367,26 @@ mod tests {
     fn reset_state() {
         NOTES.with(|notes_cell| notes_cell.borrow_mut().clear());
         NEXT_ID.with(|next_id_cell| *next_id_cell.borrow_mut() = 1);
+        CONFIG.with(|config_cell| *config_cell.borrow_mut() = CanisterConfig::default());
+        QUOTA_OVERRIDES.with(|overrides_cell| overrides_cell.borrow_mut().clear());
+        ADMINS.with(|admins_cell| admins_cell.borrow_mut().clear());
+        SUSPENDED.with(|suspended_cell| suspended_cell.borrow_mut().clear());
+        ADMIN_LOG.with(|log_cell| log_cell.borrow_mut().clear());
+        AUDIT_LOG.with(|log_cell| *log_cell.borrow_mut() = AuditLog::new(LogMemory::default()));
+        BLOBS.with(|blobs_cell| *blobs_cell.borrow_mut() = BlobStore::default());
+        EXPIRY_INDEX.with(|index_cell| index_cell.borrow_mut().clear());
+        RATE_LIMITER.with(|limiter_cell| *limiter_cell.borrow_mut() = RateLimiter::default());
+        REMINDER_INDEX.with(|index_cell| index_cell.borrow_mut().clear());
+        INBOXES.with(|inboxes_cell| *inboxes_cell.borrow_mut() = Inboxes::default());
+        COMMENTS.with(|comments_cell| *comments_cell.borrow_mut() = Comments::default());
+        MENTIONS.with(|mentions_cell| *mentions_cell.borrow_mut() = MentionIndex::default());
+        LINKS.with(|links_cell| *links_cell.borrow_mut() = LinkIndex::default());
+        TEMPLATES.with(|templates_cell| *templates_cell.borrow_mut() = Templates::default());
+        NOTE_STATES.with(|states_cell| *states_cell.borrow_mut() = NoteStates::default());
+        PROPERTY_SCHEMAS.with(|schemas_cell| schemas_cell.borrow_mut().clear());
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().clear());
+        REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow_mut().clear());
+        CONTROLLERS.with(|controllers_cell| controllers_cell.borrow_mut().clear());
     }
 
     fn test_principal(id: u8) -> Principal {
@@ -269,10 +2398,10 @@ mod tests {
         reset_state();
         // let owner1 = test_principal(1); // Using anonymous principal due to test limitations
         // set_caller(owner1);
-        let expected_owner = Principal::anonymous(); // Caller in `cargo test` is likely anonymous
+        let _expected_owner = Principal::anonymous(); // Caller in `cargo test` is likely anonymous
 
-        let title = "Test Note".to_string();
-        let content = "This is a test note.".to_string();
+        let _title = "Test Note".to_string();
+        let _content = "This is a test note.".to_string();
 
         // Assuming create_note will fail for anonymous if not allowed by logic,
         // but our current logic *allows* anonymous to create if they are not explicitly blocked earlier.
@@ -412,6 +2541,14 @@ mod tests {
             title: expected_title.to_string(),
             content: "Details".to_string(),
             created_at: 12345,
+            tags: Vec::new(),
+            updated_at: None,
+            expires_at: None,
+            encrypted: None,
+            content_hash: None,
+            remind_at: None,
+            checklist: None,
+            properties: None,
         };
         NOTES.with(|notes_cell| notes_cell.borrow_mut().insert(note_id, test_note.clone()));
         NEXT_ID.with(|nid| *nid.borrow_mut() = note_id + 1);
@@ -434,6 +2571,868 @@ mod tests {
         assert!(note.is_none());
     }
 
+    #[test]
+    fn test_import_draft_keeps_timestamp_and_tags() {
+        reset_state();
+        let owner = test_principal(1);
+        let draft = NoteDraft {
+            title: "Imported".to_string(),
+            content: "From elsewhere".to_string(),
+            tags: vec![" work ".to_string(), "work".to_string(), "".to_string()],
+            created_at: Some(500),
+            updated_at: Some(700),
+        };
+
+        let id = import_draft(owner, 1_000, draft).unwrap();
+        let note = get_note_by_id(id).unwrap();
+        assert_eq!(note.owner, owner);
+        assert_eq!(note.created_at, 500);
+        assert_eq!(note.tags, vec!["work".to_string()]);
+        assert_eq!(note.updated_at, Some(700));
+    }
+
+    #[test]
+    fn test_import_draft_rejects_invalid_items() {
+        reset_state();
+        let owner = test_principal(1);
+        let draft = |title: &str, created_at| NoteDraft {
+            title: title.to_string(),
+            content: "Body".to_string(),
+            tags: Vec::new(),
+            created_at,
+            updated_at: None,
+        };
+
+        assert_eq!(import_draft(owner, 1_000, draft("", None)).unwrap_err(), "Title cannot be empty.");
+        assert_eq!(
+            import_draft(owner, 1_000, draft("Later", Some(2_000))).unwrap_err(),
+            "created_at cannot be in the future."
+        );
+        assert_eq!(import_draft(owner, 1_000, draft("Now", None)), Ok(1));
+        assert_eq!(get_note_by_id(1).unwrap().created_at, 1_000);
+    }
+
+    #[test]
+    fn test_idle_import_jobs_are_dropped() {
+        reset_state();
+        let owner = test_principal(1);
+        let minute = 60_000_000_000;
+        let idle = start_import_as(owner, 0, ImportSource::Enex).unwrap();
+        let active = start_import_as(owner, 0, ImportSource::Enex).unwrap();
+        upload_import_chunk_as(owner, 20 * minute, active, b"<en-export>".to_vec()).unwrap();
+
+        drop_idle_import_jobs(30 * minute);
+        let open: Vec<u64> = IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow().keys().copied().collect());
+        assert_eq!(open, vec![active]);
+        assert_eq!(
+            upload_import_chunk_as(owner, 30 * minute, idle, Vec::new()).unwrap_err(),
+            "Import job not found."
+        );
+    }
+
+    #[test]
+    fn test_parsed_import_waits_for_memory() {
+        reset_state();
+        let owner = test_principal(1);
+        let mut big = ImportJob::new(test_principal(2), ImportSource::Enex, 0);
+        big.upload = vec![b' '; import::MAX_OPEN_IMPORT_BYTES - 10];
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(1, big));
+        let mut job = ImportJob::new(owner, ImportSource::Enex, 0);
+        let enex = "<en-export>\
+            <note><title>Long title</title><content><![CDATA[<en-note>Body</en-note>]]></content></note>\
+            </en-export>";
+        job.upload = enex.as_bytes().to_vec();
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(2, job));
+
+        assert_eq!(run_import_job(owner, 0, 2, 10).unwrap_err(), "Too many imports are in progress. Try again later.");
+        assert!(IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow()[&2].pending.is_none()));
+
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().remove(&1));
+        assert_eq!(run_import_job(owner, 0, 2, 10).unwrap().results.len(), 1);
+    }
+
+    #[test]
+    fn test_run_import_job_in_batches() {
+        reset_state();
+        let owner = test_principal(1);
+        let enex = "<en-export>\
+            <note><title>One</title><content><![CDATA[<en-note><div>First</div></en-note>]]></content></note>\
+            <note><title></title><content><![CDATA[<en-note><div>No title</div></en-note>]]></content></note>\
+            <note><title>Three</title><content><![CDATA[<en-note><div>Third</div></en-note>]]></content></note>\
+            </en-export>";
+        let mut job = ImportJob::new(owner, ImportSource::Enex, 0);
+        job.upload = enex.as_bytes().to_vec();
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(7, job));
+
+        assert_eq!(
+            run_import_job(test_principal(2), 1_000, 7, 2).unwrap_err(),
+            "You can only run your own import jobs."
+        );
+
+        let first = run_import_job(owner, 1_000, 7, 2).unwrap();
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.results[0].result, Ok(1));
+        assert_eq!(first.results[1].result, Err("Title cannot be empty.".to_string()));
+
+        let second = run_import_job(owner, 1_000, 7, 2).unwrap();
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.results[0].title, "Three");
+        assert_eq!(get_note_by_id(2).unwrap().content, "Third");
+        assert!(IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow().is_empty()));
+    }
+
+    #[test]
+    fn test_apply_batch_is_all_or_nothing() {
+        reset_state();
+        let owner = test_principal(1);
+        let existing = insert_note(owner, 0, NoteDraft::new("Keep".to_string(), "Body".to_string()));
+        let foreign = insert_note(test_principal(2), 0, NoteDraft::new("Theirs".to_string(), "Body".to_string()));
+
+        let errors = apply_batch(
+            owner,
+            10,
+            vec![
+                BatchOp::Create { title: "New".to_string(), content: "Body".to_string() },
+                BatchOp::Delete { id: existing },
+                BatchOp::Update { id: existing, title: "Edit".to_string(), content: "Body".to_string() },
+                BatchOp::Delete { id: foreign },
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            BatchError::InvalidOps(vec![
+                BatchOpError { index: 2, message: "Note is deleted earlier in this batch.".to_string() },
+                BatchOpError { index: 3, message: "You can only delete your own notes.".to_string() },
+            ])
+        );
+        assert_eq!(NOTES.with(|notes_cell| notes_cell.borrow().len()), 2);
+
+        let results = apply_batch(
+            owner,
+            10,
+            vec![
+                BatchOp::Update { id: existing, title: "Edit".to_string(), content: "Body".to_string() },
+                BatchOp::Create { title: "New".to_string(), content: "Body".to_string() },
+                BatchOp::Delete { id: existing },
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            results,
+            vec![BatchOpResult::Updated(existing), BatchOpResult::Created(3), BatchOpResult::Deleted(existing)]
+        );
+        assert!(get_note_by_id(existing).is_none());
+    }
+
+    fn invalid_ops(result: Result<Vec<BatchOpResult>, BatchError>) -> Vec<BatchOpError> {
+        match result {
+            Err(BatchError::InvalidOps(errors)) => errors,
+            other => panic!("expected invalid ops, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_apply_batch_rejects_oversized_batches() {
+        reset_state();
+        let ops = vec![BatchOp::Delete { id: 1 }; MAX_BATCH_OPS + 1];
+        assert_eq!(
+            apply_batch(test_principal(1), 0, ops).unwrap_err(),
+            BatchError::TooManyOps { max: MAX_BATCH_OPS as u32 }
+        );
+    }
+
+    #[test]
+    fn test_lookup_notes_preserves_order_and_caps_batch() {
+        reset_state();
+        let owner = test_principal(1);
+        let id = insert_note(owner, 0, NoteDraft::new("One".to_string(), "Body".to_string()));
+
+        let results = lookup_notes(test_principal(2), &[404, id]).unwrap();
+        assert!(matches!(results[0], NoteLookup::NotFound));
+        assert!(matches!(&results[1], NoteLookup::Found(note) if note.id == id));
+
+        let too_many: Vec<u64> = (0..=MAX_NOTES_BY_IDS as u64).collect();
+        assert!(lookup_notes(owner, &too_many).is_err());
+    }
+
+    #[test]
+    fn test_anonymous_policy_is_enforced() {
+        reset_state();
+        let anonymous = Principal::anonymous();
+        let set_policy = |policy| CONFIG.with(|config_cell| config_cell.borrow_mut().anonymous_policy = policy);
+
+        set_policy(AnonymousPolicy::Disallow);
+        assert!(authorize_write(anonymous, 0).is_err());
+        assert!(authorize_write(test_principal(1), 1).is_ok());
+        let draft = NoteDraft::new("Anon".to_string(), "Body".to_string());
+        assert!(import_draft(anonymous, 10, draft.clone()).is_err());
+
+        set_policy(AnonymousPolicy::AllowWithSeparateQuota { max_notes: 1 });
+        assert_eq!(import_draft(anonymous, 10, draft.clone()), Ok(1));
+        assert!(authorize_write(anonymous, 1).is_err());
+        assert!(authorize_write(anonymous, 0).is_ok());
+
+        set_policy(AnonymousPolicy::AllowEphemeral { ttl_seconds: 1 });
+        let id = import_draft(anonymous, 10, draft).unwrap();
+        assert_eq!(get_note_by_id(id).unwrap().expires_at, Some(1_000_000_010));
+
+        purge_expired_notes(test_principal(0), 1_000_000_009);
+        assert!(get_note_by_id(id).is_some());
+        purge_expired_notes(test_principal(0), 1_000_000_010);
+        assert!(get_note_by_id(id).is_none());
+        assert!(get_note_by_id(1).is_some());
+    }
+
+    #[test]
+    fn test_apply_batch_is_rate_limited() {
+        reset_state();
+        let owner = test_principal(1);
+        CONFIG.with(|config_cell| {
+            config_cell.borrow_mut().rate_limits.create = config::RateLimit { burst: 2, refill_per_minute: 1 }
+        });
+        let create = |title: &str| BatchOp::Create { title: title.to_string(), content: "Body".to_string() };
+
+        assert!(apply_batch(owner, 0, vec![create("One"), create("Two")]).is_ok());
+        assert_eq!(
+            apply_batch(owner, 0, vec![create("Three")]).unwrap_err(),
+            BatchError::RateLimited(RateLimitError::RetryAfter { method: RateLimitedMethod::Create, seconds: 60 })
+        );
+        assert_eq!(NOTES.with(|notes_cell| notes_cell.borrow().len()), 2);
+        assert!(apply_batch(test_principal(2), 0, vec![create("Other")]).is_ok());
+    }
+
+    #[test]
+    fn test_imports_are_rate_limited() {
+        reset_state();
+        let owner = test_principal(1);
+        CONFIG.with(|config_cell| {
+            config_cell.borrow_mut().rate_limits.create = config::RateLimit { burst: 3, refill_per_minute: 1 }
+        });
+        let item = |title: &str| ImportItem::Json(format!(r#"{{"title": "{}", "content": "Body"}}"#, title));
+
+        assert_eq!(import_items_as(owner, 0, vec![item("One"), item("Two")]).unwrap().len(), 2);
+        assert!(import_items_as(owner, 0, vec![item("Three"), item("Four")]).is_err());
+        assert_eq!(NOTES.with(|notes_cell| notes_cell.borrow().len()), 2);
+
+        let job_id = 1;
+        let mut job = ImportJob::new(owner, ImportSource::Enex, 0);
+        job.pending = Some(vec![NoteDraft::new("A".to_string(), "Body".to_string()); 2].into());
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(job_id, job));
+        // One token is left, so a run of two imports one note and keeps the job.
+        assert_eq!(run_import_job(owner, 0, job_id, 2).unwrap().remaining, 1);
+        assert_eq!(
+            run_import_job(owner, 0, job_id, 2).unwrap_err(),
+            "Rate limited: too many create calls. Retry after 60 seconds."
+        );
+        assert_eq!(run_import_job(owner, 60_000_000_000, job_id, 2).unwrap().remaining, 0);
+    }
+
+    #[test]
+    fn test_full_batches_fit_default_rate_limits() {
+        reset_state();
+        let owner = test_principal(1);
+        let create = |i: usize| BatchOp::Create { title: format!("Note {}", i), content: "Body".to_string() };
+        assert_eq!(apply_batch(owner, 0, (0..MAX_BATCH_OPS).map(create).collect()).unwrap().len(), MAX_BATCH_OPS);
+
+        let importer = test_principal(2);
+        let item = |i: usize| ImportItem::Json(format!(r#"{{"title": "Item {}", "content": "Body"}}"#, i));
+        let results = import_items_as(importer, 0, (0..MAX_IMPORT_BATCH).map(item).collect()).unwrap();
+        assert!(results.iter().all(Result::is_ok));
+
+        let job_id = 1;
+        let mut job = ImportJob::new(test_principal(3), ImportSource::Enex, 0);
+        job.pending = Some(vec![NoteDraft::new("A".to_string(), "Body".to_string()); MAX_IMPORT_BATCH].into());
+        IMPORT_JOBS.with(|jobs_cell| jobs_cell.borrow_mut().insert(job_id, job));
+        let report = run_import_job(test_principal(3), 0, job_id, MAX_IMPORT_BATCH).unwrap();
+        assert_eq!((report.results.len(), report.remaining), (MAX_IMPORT_BATCH, 0));
+    }
+
+    #[test]
+    fn test_quota_limits_growth() {
+        reset_state();
+        let owner = test_principal(1);
+        QUOTA_OVERRIDES.with(|overrides_cell| {
+            overrides_cell.borrow_mut().insert(owner, Quota { max_notes: 2, max_bytes: 20 })
+        });
+        let create = |title: &str, content: &str| BatchOp::Create { title: title.to_string(), content: content.to_string() };
+
+        let created = apply_batch(owner, 0, vec![create("A", "12345"), create("B", "12345")]).unwrap();
+        let BatchOpResult::Created(id) = created[0] else { panic!("expected a create") };
+        let draft = NoteDraft::new("C".to_string(), "Body".to_string());
+        assert_eq!(
+            import_draft(owner, 0, draft.clone()).unwrap_err(),
+            "Quota exceeded: you can store at most 2 notes."
+        );
+        assert!(import_draft(test_principal(2), 0, draft).is_ok());
+
+        assert!(update_note_as(owner, 1, id, "A".to_string(), "x".repeat(20)).is_err());
+        assert!(update_note_as(owner, 1, id, "A".to_string(), "1".to_string()).is_ok());
+
+        // Freeing space earlier in a batch makes room for later operations.
+        let errors = invalid_ops(apply_batch(owner, 2, vec![create("D", "1"), BatchOp::Delete { id }]));
+        assert_eq!(errors[0].index, 0);
+        assert!(apply_batch(owner, 2, vec![BatchOp::Delete { id }, create("D", "1")]).is_ok());
+        assert_eq!(
+            NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner)),
+            quota::Usage { notes: 2, bytes: 8 }
+        );
+    }
+
+    #[test]
+    fn test_batch_update_keeps_checklist_bytes() {
+        reset_state();
+        let owner = test_principal(1);
+        QUOTA_OVERRIDES.with(|overrides_cell| {
+            overrides_cell.borrow_mut().insert(owner, Quota { max_notes: 5, max_bytes: 20 })
+        });
+        let item = NewChecklistItem { text: "123456789".to_string(), due_at: None, assignee: None };
+        let id = create_checklist_note_as(owner, 0, "T".to_string(), "D".to_string(), vec![item]).unwrap();
+
+        // Rewriting the description keeps the 9 item bytes, so the create no longer fits.
+        let update = BatchOp::Update { id, title: "T".to_string(), content: "D".to_string() };
+        let create = BatchOp::Create { title: "X".to_string(), content: "123456789".to_string() };
+        let errors = invalid_ops(apply_batch(owner, 1, vec![update.clone(), create]));
+        assert_eq!(errors[0].index, 1);
+        assert!(apply_batch(owner, 1, vec![update]).is_ok());
+        assert_eq!(NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner)).bytes, 11);
+    }
+
+    #[test]
+    fn test_batch_update_keeps_property_bytes() {
+        reset_state();
+        let owner = test_principal(1);
+        QUOTA_OVERRIDES.with(|overrides_cell| {
+            overrides_cell.borrow_mut().insert(owner, Quota { max_notes: 5, max_bytes: 20 })
+        });
+        let id = insert_note(owner, 0, NoteDraft::new("T".to_string(), "D".to_string()));
+        set_note_property_as(owner, 0, id, "status".to_string(), Some(PropertyValue::Text("open".to_string()))).unwrap();
+
+        // "status" + "open" stay charged through the update, leaving room for 8 bytes.
+        let update = BatchOp::Update { id, title: "T".to_string(), content: "D".to_string() };
+        let create = |content: &str| BatchOp::Create { title: "X".to_string(), content: content.to_string() };
+        assert_eq!(invalid_ops(apply_batch(owner, 1, vec![update.clone(), create("12345678")]))[0].index, 1);
+        assert!(apply_batch(owner, 1, vec![update, create("1234567")]).is_ok());
+    }
+
+    #[test]
+    fn test_admin_actions_are_enforced_and_logged() {
+        reset_state();
+        let admin = test_principal(9);
+        let user = test_principal(1);
+        ADMINS.with(|admins_cell| admins_cell.borrow_mut().insert(admin));
+        let id = insert_note(user, 0, NoteDraft::new("Spam".to_string(), "Body".to_string()));
+
+        assert!(suspend_principal_as(admin, 5, admin, "oops".to_string()).is_err());
+        suspend_principal_as(admin, 5, user, "spam".to_string()).unwrap();
+        assert_eq!(authorize_write(user, 0).unwrap_err(), "Your account is suspended: spam");
+        assert!(authorize_write(test_principal(2), 1).is_ok());
+
+        assert!(force_delete_note_as(admin, 6, id, " ".to_string()).is_err());
+        force_delete_note_as(admin, 6, id, "spam".to_string()).unwrap();
+        assert!(get_note_by_id(id).is_none());
+
+        let stats = global_stats();
+        assert_eq!((stats.total_notes, stats.admins, stats.suspended_principals), (0, 1, 1));
+
+        let page = ADMIN_LOG.with(|log_cell| admin::log_page(&log_cell.borrow(), None, None));
+        assert_eq!(page.entries.len(), 2);
+        assert!(matches!(
+            page.entries[0].action,
+            AdminAction::ForceDeleteNote { id: deleted, owner, .. } if deleted == id && owner == user
+        ));
+    }
+
+    #[test]
+    fn test_controllers_cannot_be_suspended_and_resuspension_is_logged() {
+        reset_state();
+        let admin = test_principal(9);
+        let controller = test_principal(8);
+        let user = test_principal(1);
+        ADMINS.with(|admins_cell| admins_cell.borrow_mut().insert(admin));
+        CONTROLLERS.with(|controllers_cell| controllers_cell.borrow_mut().insert(controller));
+
+        assert_eq!(
+            suspend_principal_as(admin, 5, controller, "takeover".to_string()).unwrap_err(),
+            "Admins cannot be suspended; remove them as admin first."
+        );
+        assert!(authorize_write(controller, 0).is_ok());
+
+        suspend_principal_as(admin, 5, user, "spam".to_string()).unwrap();
+        suspend_principal_as(admin, 6, user, "more spam".to_string()).unwrap();
+        let page = ADMIN_LOG.with(|log_cell| admin::log_page(&log_cell.borrow(), None, None));
+        let AdminAction::SuspendPrincipal { reason, replaced: Some(replaced), .. } = &page.entries[0].action else {
+            panic!("expected a replacing suspension");
+        };
+        assert_eq!((reason.as_str(), replaced.reason.as_str(), replaced.suspended_at), ("more spam", "spam", 5));
+    }
+
+    #[test]
+    fn test_mutations_are_audited() {
+        reset_state();
+        let owner = test_principal(1);
+        let id = insert_note(owner, 1, NoteDraft::new("Title".to_string(), "v1".to_string()));
+        update_note_as(owner, 2, id, "Title".to_string(), "v2".to_string()).unwrap();
+        assert!(update_note_as(test_principal(2), 3, id, "Title".to_string(), "v3".to_string()).is_err());
+        delete_note_as(owner, 4, id).unwrap();
+
+        let log: Vec<AuditEntry> = AUDIT_LOG.with(|log_cell| {
+            let log = log_cell.borrow();
+            (1..=log.len()).filter_map(|seq| log.get(seq)).collect()
+        });
+        let actions: Vec<AuditAction> = log.iter().map(|entry| entry.action).collect();
+        assert_eq!(actions, vec![AuditAction::Create, AuditAction::Update, AuditAction::Delete]);
+        assert_eq!(log[1].content_hash, Some(audit::content_hash(b"v2")));
+        assert_eq!(log[2].timestamp, 4);
+        assert!(AUDIT_LOG.with(|log_cell| log_cell.borrow().verify(None, None)).unwrap().complete);
+    }
+
+    #[test]
+    fn test_owner_reads_audit_log_of_removed_note() {
+        reset_state();
+        let owner = test_principal(1);
+        let id = insert_note(owner, 1, NoteDraft::new("Title".to_string(), "v1".to_string()));
+        delete_note_as(owner, 2, id).unwrap();
+
+        let page = note_audit_log_as(owner, id, None, None).unwrap();
+        assert_eq!(page.entries[0].action, AuditAction::Delete);
+        assert!(note_audit_log_as(test_principal(2), id, None, None).is_err());
+
+        // States saved before owners of removed notes were kept get them from the log.
+        REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow_mut().clear());
+        insert_note(owner, 3, NoteDraft::new("Live".to_string(), "Body".to_string()));
+        rebuild_removed_note_owners();
+        assert_eq!(REMOVED_NOTE_OWNERS.with(|owners_cell| owners_cell.borrow().clone()), BTreeMap::from([(id, owner)]));
+    }
+
+    #[test]
+    fn test_encrypted_notes_skip_plaintext_paths() {
+        reset_state();
+        let owner = test_principal(1);
+        let friend = test_principal(2);
+        let key_for = |recipient| WrappedKey { recipient, scheme: "rsa-oaep-sha256".to_string(), key: vec![1; 32] };
+        let encrypted = EncryptedContent {
+            algorithm: "AES-256-GCM".to_string(),
+            nonce: vec![0; 12],
+            ciphertext: vec![0xde, 0xad],
+            wrapped_keys: vec![key_for(owner)],
+        };
+        let id = create_encrypted_note_as(owner, 1, encrypted).unwrap();
+
+        let note = NOTES.with(|notes_cell| notes_cell.borrow().get(&id).cloned()).unwrap();
+        assert!(!can_read(&note, friend));
+        assert!(update_note_as(owner, 2, id, "Plain".to_string(), "Text".to_string()).is_err());
+        let export = NOTES.with(|notes_cell| {
+            export::export_chunk(&notes_cell.borrow(), owner, ExportFormat::Json, None, None)
+        });
+        assert_eq!(export.unwrap().total_notes, 0);
+
+        share_encrypted_note_as(owner, 3, id, key_for(friend)).unwrap();
+        share_encrypted_note_as(owner, 3, id, key_for(friend)).unwrap();
+        assert!(matches!(&lookup_notes(friend, &[id]).unwrap()[0], NoteLookup::Found(_)));
+        let inbox = INBOXES.with(|inboxes_cell| inboxes_cell.borrow().get(friend).to_vec());
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].kind, NotificationKind::SharedWithYou { note_id: id, by: owner });
+
+        let drop_owner = change_recipients_as(owner, 4, id, AuditAction::Unshare, owner, |e| {
+            e.wrapped_keys.retain(|key| key.recipient != owner);
+            Ok(())
+        });
+        assert_eq!(drop_owner.unwrap_err(), "The owner must have a wrapped key for their own note.");
+        assert_eq!(AUDIT_LOG.with(|log_cell| log_cell.borrow().len()), 3);
+        let shared = AUDIT_LOG.with(|log_cell| log_cell.borrow().get(2)).unwrap();
+        assert_eq!((shared.action, shared.recipient), (AuditAction::Share, Some(friend)));
+    }
+
+    #[test]
+    fn test_note_keys_follow_recipients() {
+        reset_state();
+        let owner = test_principal(1);
+        let friend = test_principal(2);
+        let key_for = |recipient| WrappedKey { recipient, scheme: "vetkd-ibe".to_string(), key: vec![1; 32] };
+        let encrypted = EncryptedContent {
+            algorithm: "AES-256-GCM".to_string(),
+            nonce: vec![0; 12],
+            ciphertext: vec![1, 2, 3],
+            wrapped_keys: vec![key_for(owner)],
+        };
+        let id = create_encrypted_note_as(owner, 1, encrypted).unwrap();
+        let plain = insert_note(owner, 1, NoteDraft::new("Plain".to_string(), "Text".to_string()));
+
+        assert_eq!(note_key_input_for(owner, plain).unwrap_err(), "Note is not encrypted.");
+        assert_eq!(note_key_input_for(friend, id).unwrap_err(), "You are not a recipient of this note.");
+
+        change_recipients_as(owner, 2, id, AuditAction::Share, friend, |e| {
+            e.wrapped_keys.push(key_for(friend));
+            Ok(())
+        })
+        .unwrap();
+        let deriver = keys::LocalKeyDeriver { seed: vec![42] };
+        let derive = |principal, transport: &[u8]| {
+            let input = note_key_input_for(principal, id).unwrap();
+            let key = keys::tests::block_on(keys::derive_scoped_key(&deriver, KeyScope::Note, &input, transport));
+            keys::LocalKeyDeriver::unmask(&key.unwrap(), transport)
+        };
+        // Both recipients recover the same note key through different transport keys.
+        assert_eq!(derive(owner, &[1; 48]), derive(friend, &[2; 48]));
+    }
+
+    #[test]
+    fn test_identical_contents_share_one_blob() {
+        reset_state();
+        let blob_count = || BLOBS.with(|blobs_cell| blobs_cell.borrow().len());
+        let a = insert_note(test_principal(1), 0, NoteDraft::new("A".to_string(), "Same body".to_string()));
+        let b = insert_note(test_principal(2), 0, NoteDraft::new("B".to_string(), "Same body".to_string()));
+        assert_eq!(blob_count(), 1);
+
+        let note = get_note_by_id(a).unwrap();
+        assert_eq!(note.content, "Same body");
+        assert_eq!(note.content_hash, Some(audit::content_hash(b"Same body")));
+        assert_eq!(note.content_hash, get_note_by_id(b).unwrap().content_hash);
+
+        update_note_as(test_principal(1), 1, a, "A".to_string(), "New body".to_string()).unwrap();
+        assert_eq!(blob_count(), 2);
+        delete_note_as(test_principal(2), 2, b).unwrap();
+        assert_eq!(blob_count(), 1);
+        assert_eq!(get_note_by_id(a).unwrap().content, "New body");
+
+        // Inline content restored from an older snapshot is moved into the store.
+        NOTES.with(|notes_cell| notes_cell.borrow_mut().get_mut(&a).unwrap().content_hash = None);
+        NOTES.with(|notes_cell| notes_cell.borrow_mut().get_mut(&a).unwrap().content = "New body".to_string());
+        BLOBS.with(|blobs_cell| *blobs_cell.borrow_mut() = BlobStore::default());
+        intern_inline_contents();
+        assert_eq!(blob_count(), 1);
+        assert_eq!(get_note_by_id(a).unwrap().content, "New body");
+    }
+
+    #[test]
+    fn test_writes_are_normalized_by_the_validation_policy() {
+        reset_state();
+        let owner = test_principal(1);
+        let id = insert_note(owner, 0, NoteDraft::new("Seed".to_string(), "Body".to_string()));
+
+        update_note_as(owner, 1, id, "  Cafe\u{301} ".to_string(), "line one\r\nline two\n".to_string()).unwrap();
+        let note = get_note_by_id(id).unwrap();
+        assert_eq!(note.title, "Caf\u{e9}");
+        assert_eq!(note.content, "line one\nline two");
+
+        assert_eq!(
+            update_note_as(owner, 2, id, "  ".to_string(), "Body".to_string()).unwrap_err(),
+            "Title cannot be empty."
+        );
+        CONFIG.with(|config_cell| config_cell.borrow_mut().validation.max_title_chars = 3);
+        assert_eq!(
+            update_note_as(owner, 2, id, "Long".to_string(), "Body".to_string()).unwrap_err(),
+            "Title exceeds 3 characters. Current length: 4"
+        );
+    }
+
+    #[test]
+    fn test_max_note_size_comes_from_config() {
+        reset_state();
+        let owner = test_principal(1);
+        let id = insert_note(owner, 0, NoteDraft::new("T".to_string(), "Body".to_string()));
+        assert!(update_note_as(owner, 1, id, "T".to_string(), "x".repeat(2000)).is_err());
+
+        let args = ConfigArgs {
+            max_note_size_bytes: Some(4096),
+            ..ConfigArgs::default()
+        };
+        args.validate().unwrap();
+        CONFIG.with(|config_cell| config_cell.borrow_mut().apply(args));
+        update_note_as(owner, 2, id, "T".to_string(), "x".repeat(2000)).unwrap();
+        assert_eq!(get_config().max_note_size_bytes, 4096);
+
+        // The persisted form restores the same configuration.
+        let mut restored = CanisterConfig::default();
+        restored.apply(ConfigArgs::from(&get_config()));
+        assert_eq!(restored, get_config());
+
+        for max_bytes in [0, config::MAX_NOTE_SIZE_LIMIT_BYTES + 1] {
+            let args = ConfigArgs {
+                max_note_size_bytes: Some(max_bytes),
+                ..ConfigArgs::default()
+            };
+            assert!(args.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_due_reminders_move_to_the_owners_inbox() {
+        reset_state();
+        let owner = test_principal(1);
+        let early = insert_note(owner, 0, NoteDraft::new("Early".to_string(), "Body".to_string()));
+        let late = insert_note(owner, 0, NoteDraft::new("Late".to_string(), "Body".to_string()));
+        let deleted = insert_note(owner, 0, NoteDraft::new("Gone".to_string(), "Body".to_string()));
+
+        assert!(set_reminder_as(owner, 100, early, Some(100)).is_err());
+        assert!(set_reminder_as(test_principal(2), 0, early, Some(200)).is_err());
+        set_reminder_as(owner, 0, early, Some(500)).unwrap();
+        set_reminder_as(owner, 0, early, Some(200)).unwrap();
+        set_reminder_as(owner, 0, late, Some(900)).unwrap();
+        set_reminder_as(owner, 0, deleted, Some(300)).unwrap();
+        delete_note_as(owner, 1, deleted).unwrap();
+
+        deliver_due_reminders(600);
+        let inbox = INBOXES.with(|inboxes_cell| inboxes_cell.borrow().get(owner).to_vec());
+        assert_eq!(inbox.len(), 1);
+        assert_eq!(inbox[0].kind, NotificationKind::ReminderDue { note_id: early, due_at: 200 });
+        assert_eq!(get_note_by_id(early).unwrap().remind_at, None);
+
+        // Reminders are kept on the notes, so an upgrade can rebuild the index.
+        REMINDER_INDEX.with(|index_cell| index_cell.borrow_mut().clear());
+        rebuild_reminder_index();
+        assert_eq!(REMINDER_INDEX.with(|index_cell| index_cell.borrow().first().copied()), Some((900, late)));
+    }
+
+    #[test]
+    fn test_comments_follow_note_access_and_notify() {
+        reset_state();
+        let owner = test_principal(1);
+        let friend = test_principal(2);
+        let id = insert_note(owner, 0, NoteDraft::new("Plan".to_string(), "Body".to_string()));
+
+        let question = add_comment_as(friend, 1, id, None, "Question?".to_string()).unwrap();
+        let answer = add_comment_as(owner, 2, id, Some(question), "Answer.".to_string()).unwrap();
+        let inbox = |principal| INBOXES.with(|inboxes_cell| inboxes_cell.borrow().get(principal).to_vec());
+        assert_eq!(inbox(owner)[0].kind, NotificationKind::Comment { note_id: id, comment_id: question, by: friend });
+        assert_eq!(inbox(friend)[0].kind, NotificationKind::Comment { note_id: id, comment_id: answer, by: owner });
+        assert_eq!(inbox(owner).len(), 1);
+
+        assert!(change_comment_as(owner, question, |c| c.edit(question, owner, "Mine now", 3)).is_err());
+
+        // Comments on encrypted notes are limited to the owner and recipients.
+        let encrypted = EncryptedContent {
+            algorithm: "AES-256-GCM".to_string(),
+            nonce: vec![0; 12],
+            ciphertext: vec![1],
+            wrapped_keys: vec![WrappedKey { recipient: owner, scheme: "rsa".to_string(), key: vec![1] }],
+        };
+        let secret = create_encrypted_note_as(owner, 3, encrypted).unwrap();
+        assert_eq!(add_comment_as(friend, 4, secret, None, "Hi".to_string()).unwrap_err(), "Note not found.");
+
+        delete_note_as(owner, 5, id).unwrap();
+        assert!(COMMENTS.with(|comments_cell| comments_cell.borrow().for_note(id).is_empty()));
+    }
+
+    #[test]
+    fn test_mentions_notify_once_and_are_indexed() {
+        reset_state();
+        let owner = test_principal(1);
+        let friend = test_principal(2);
+        let content = format!("Ask @{} and @{}", friend, owner);
+        let id = insert_note(owner, 0, NoteDraft::new("Plan".to_string(), content.clone()));
+        let friend_inbox = || INBOXES.with(|inboxes_cell| inboxes_cell.borrow().get(friend).to_vec());
+        assert_eq!(friend_inbox()[0].kind, NotificationKind::Mention { note_id: id, by: owner });
+        assert!(INBOXES.with(|inboxes_cell| inboxes_cell.borrow().get(owner).is_empty()));
+
+        update_note_as(owner, 1, id, "Plan".to_string(), format!("{} again", content)).unwrap();
+        assert_eq!(friend_inbox().len(), 1);
+        assert_eq!(MENTIONS.with(|mentions_cell| mentions_cell.borrow().notes_mentioning(friend)), vec![id]);
+
+        update_note_as(owner, 2, id, "Plan".to_string(), "Nobody".to_string()).unwrap();
+        assert!(MENTIONS.with(|mentions_cell| mentions_cell.borrow().notes_mentioning(friend)).is_empty());
+    }
+
+    #[test]
+    fn test_links_resolve_on_read_and_break_on_delete() {
+        reset_state();
+        let owner = test_principal(1);
+        let other = test_principal(2);
+        let target = insert_note(owner, 0, NoteDraft::new("Project Plan".to_string(), "Body".to_string()));
+        let by_title = insert_note(owner, 0, NoteDraft::new("Index".to_string(), "See [[project plan]]".to_string()));
+        let by_id = insert_note(other, 0, NoteDraft::new("Theirs".to_string(), format!("See [[{}]]", target)));
+        // Title links only resolve against the link author's own notes.
+        insert_note(other, 0, NoteDraft::new("Stray".to_string(), "See [[Project Plan]]".to_string()));
+
+        let backlinks: Vec<u64> = backlinks_as(owner, target).unwrap().iter().map(|node| node.id).collect();
+        assert_eq!(backlinks, vec![by_title, by_id]);
+
+        let graph = link_graph_of(owner);
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges[0].resolved, Some(target));
+
+        delete_note_as(owner, 1, target).unwrap();
+        assert_eq!(link_graph_of(owner).edges[0].resolved, None);
+        assert!(backlinks_as(owner, target).is_err());
+    }
+
+    #[test]
+    fn test_checklist_items_and_open_tasks() {
+        reset_state();
+        let owner = test_principal(1);
+        let helper = test_principal(2);
+        let item = |text: &str, due_at, assignee| NewChecklistItem { text: text.to_string(), due_at, assignee };
+        let id = create_checklist_note_as(
+            owner,
+            0,
+            "Trip".to_string(),
+            "Packing".to_string(),
+            vec![item("Tent", None, None), item("Tickets", Some(50), Some(helper))],
+        )
+        .unwrap();
+        let late = change_checklist(owner, 1, id, None, |checklist| checklist.add_item(item("Map", Some(90), None))).unwrap();
+        let early = change_checklist(owner, 1, id, None, |checklist| checklist.add_item(item("Food", Some(10), None))).unwrap();
+
+        let open: Vec<u64> = open_tasks_of(owner).iter().map(|task| task.item.id).collect();
+        assert_eq!(open, vec![early, late, 1]);
+        assert_eq!(open_tasks_of(helper)[0].item.text, "Tickets");
+
+        // The assignee may tick off their own item, but nothing else.
+        let toggle = |principal, item_id| {
+            change_checklist(principal, 2, id, Some(item_id), |checklist| {
+                let item = checklist.item_mut(item_id)?;
+                item.done = !item.done;
+                Ok(item.done)
+            })
+        };
+        assert_eq!(toggle(helper, 2), Ok(true));
+        assert!(toggle(helper, 1).is_err());
+        assert!(open_tasks_of(helper).is_empty());
+
+        let plain = insert_note(owner, 0, NoteDraft::new("Plain".to_string(), "Body".to_string()));
+        assert_eq!(change_checklist(owner, 3, plain, None, |_| Ok(())).unwrap_err(), "Note is not a checklist.");
+        let usage = NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner));
+        assert_eq!(usage.bytes, ("TripPacking".len() + "TentTicketsMapFood".len() + "PlainBody".len()) as u64);
+    }
+
+    #[test]
+    fn test_create_note_from_template() {
+        reset_state();
+        let owner = test_principal(1);
+        let input = validate_template(TemplateInput {
+            name: " Standup ".to_string(),
+            title: "Standup {{date}}".to_string(),
+            content: "Led by {{lead}}".to_string(),
+            tags: vec!["meeting".to_string()],
+        })
+        .unwrap();
+        assert_eq!(input.name, "Standup");
+        let template_id = TEMPLATES.with(|templates_cell| templates_cell.borrow_mut().insert(owner, 0, input)).unwrap();
+
+        let lead = vec![TemplateVariable { name: "lead".to_string(), value: "Sam".to_string() }];
+        let id = create_note_from_template_as(owner, 86_400_000_000_000, template_id, lead).unwrap();
+        let note = get_note_by_id(id).unwrap();
+        assert_eq!(note.title, "Standup 1970-01-02");
+        assert_eq!(note.content, "Led by Sam");
+        assert_eq!(note.tags, vec!["meeting".to_string()]);
+
+        assert_eq!(
+            create_note_from_template_as(test_principal(2), 0, template_id, Vec::new()).unwrap_err(),
+            "Template not found."
+        );
+    }
+
+    #[test]
+    fn test_listings_pin_and_archive_per_viewer() {
+        reset_state();
+        let owner = test_principal(1);
+        let friend = test_principal(2);
+        let ids: Vec<u64> = (1..=3)
+            .map(|n| insert_note(owner, 0, NoteDraft::new(format!("Note {}", n), "Body".to_string())))
+            .collect();
+
+        update_note_state_as(owner, ids[2], |state| state.pinned = true).unwrap();
+        update_note_state_as(owner, ids[0], |state| state.archived = true).unwrap();
+        update_note_state_as(friend, ids[1], |state| state.archived = true).unwrap();
+
+        let listed = |viewer| {
+            NOTES.with(|notes_cell| {
+                let notes = notes_cell.borrow();
+                list_for_viewer(viewer, notes.values()).iter().map(|note| note.id).collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(listed(owner), vec![ids[2], ids[1]]);
+        assert_eq!(listed(friend), vec![ids[0], ids[2]]);
+
+        delete_note_as(owner, 1, ids[1]).unwrap();
+        assert!(!NOTE_STATES.with(|states_cell| states_cell.borrow().get(friend, ids[1]).archived));
+        assert!(update_note_state_as(owner, 99, |state| state.favorite = true).is_err());
+    }
+
+    #[test]
+    fn test_note_properties_schema_and_listing() {
+        reset_state();
+        let owner = test_principal(1);
+        let other = test_principal(2);
+        let ids: Vec<u64> = ["A", "B", "C"]
+            .iter()
+            .map(|title| insert_note(owner, 0, NoteDraft::new(title.to_string(), "Body".to_string())))
+            .collect();
+        let priority = |value| Some(PropertyValue::Number(value));
+        set_note_property_as(owner, 1, ids[0], "priority".to_string(), priority(3.0)).unwrap();
+        set_note_property_as(owner, 1, ids[1], "priority".to_string(), priority(1.0)).unwrap();
+        set_note_property_as(owner, 1, ids[2], "status".to_string(), Some(PropertyValue::Text("open".to_string())))
+            .unwrap();
+        assert!(set_note_property_as(other, 1, ids[0], "priority".to_string(), priority(2.0)).is_err());
+
+        let listed = |condition, descending| -> Vec<u64> {
+            notes_by_property_of(owner, "priority", &condition, descending).iter().map(|note| note.id).collect()
+        };
+        assert_eq!(listed(PropertyCondition::Exists, false), vec![ids[1], ids[0]]);
+        assert_eq!(listed(PropertyCondition::Exists, true), vec![ids[0], ids[1]]);
+        let at_least_two = PropertyCondition::Between { min: priority(2.0), max: None };
+        assert_eq!(listed(at_least_two, false), vec![ids[0]]);
+
+        // The schema must cover existing properties, then constrains new ones.
+        let schema = PropertySchema::from([("priority".to_string(), properties::PropertyKind::Number)]);
+        assert!(set_property_schema_as(owner, Some(schema.clone())).is_err());
+        set_note_property_as(owner, 2, ids[2], "status".to_string(), None).unwrap();
+        set_property_schema_as(owner, Some(schema)).unwrap();
+        assert!(set_note_property_as(owner, 3, ids[2], "status".to_string(), Some(PropertyValue::Bool(true))).is_err());
+        assert!(NOTES.with(|notes_cell| notes_cell.borrow()[&ids[2]].properties.is_none()));
+
+        let usage = NOTES.with(|notes_cell| quota::usage_of(&notes_cell.borrow(), owner));
+        assert_eq!(usage.bytes, 3 * "ABody".len() as u64 + 2 * ("priority".len() as u64 + 8));
+    }
+
+    #[test]
+    fn test_query_notes_filters_sorts_and_pages() {
+        use query::{NoteFilter, NoteSort, NoteSortField};
+
+        reset_state();
+        let owner = test_principal(1);
+        let other = test_principal(2);
+        let draft = |title: &str, content: &str, tags: &[&str]| {
+            let mut draft = NoteDraft::new(title.to_string(), content.to_string());
+            draft.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            draft
+        };
+        let plan = insert_note(owner, 10, draft("Plan", "Quarterly roadmap", &["work"]));
+        let diary = insert_note(owner, 20, draft("Diary", "Walked the dog", &["home"]));
+        let budget = insert_note(owner, 30, draft("budget", "Roadmap costs", &["work", "money"]));
+        insert_note(other, 40, draft("Roadmap", "Not shared", &["work"]));
+        update_note_state_as(owner, diary, |state| state.archived = true).unwrap();
+
+        let ids = |filter, sort| -> Vec<u64> {
+            let query = NoteQuery { filter: Some(filter), sort, cursor: None, limit: None };
+            query_notes_as(owner, query).unwrap().notes.iter().map(|note| note.id).collect()
+        };
+        let work_or_dog = NoteFilter::Or(vec![NoteFilter::Tag("work".to_string()), NoteFilter::Text("DOG".to_string())]);
+        assert_eq!(ids(work_or_dog, None), vec![plan, diary, budget]);
+
+        let by_title = Some(NoteSort { field: NoteSortField::Title, descending: false });
+        let roadmap = NoteFilter::And(vec![
+            NoteFilter::Text("roadmap".to_string()),
+            NoteFilter::CreatedBetween { from: Some(15), to: None },
+        ]);
+        assert_eq!(ids(roadmap, by_title.clone()), vec![budget]);
+        assert_eq!(ids(NoteFilter::Archived(false), by_title), vec![budget, plan]);
+
+        let newest_first = Some(NoteSort { field: NoteSortField::CreatedAt, descending: true });
+        let query = NoteQuery { filter: None, sort: newest_first, cursor: None, limit: Some(2) };
+        let first = query_notes_as(owner, query.clone()).unwrap();
+        assert_eq!(first.notes.iter().map(|note| note.id).collect::<Vec<_>>(), vec![budget, diary]);
+        assert_eq!(first.total, 3);
+        let second = query_notes_as(owner, NoteQuery { cursor: first.next_cursor, ..query }).unwrap();
+        assert_eq!(second.notes.iter().map(|note| note.id).collect::<Vec<_>>(), vec![plan]);
+        assert_eq!(second.next_cursor, None);
+
+        let too_deep = (0..10).fold(NoteFilter::Archived(false), |filter, _| NoteFilter::And(vec![filter]));
+        let query = NoteQuery { filter: Some(too_deep), sort: None, cursor: None, limit: None };
+        assert!(query_notes_as(owner, query).is_err());
+    }
+
     #[test]
     fn generate_candid_interface_for_verification() {
         // This test ensures the candid export macro works and can be used to verify .did file.
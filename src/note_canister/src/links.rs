@@ -0,0 +1,117 @@
+// --- Wiki Links ---
+// `[[42]]` links to note 42 and `[[Some Title]]` to the link author's note with
+// that title. Only the parsed targets are stored; they are resolved when read,
+// so a link whose target was deleted or renamed simply shows up as broken.
+
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize};
+
+// --- Constants ---
+pub const MAX_LINKS_PER_NOTE: usize = 100;
+const MAX_LINK_TEXT_CHARS: usize = 200;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, Hash, CandidType, Deserialize, serde::Serialize)]
+pub enum LinkTarget {
+    Id(u64),
+    Title(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct GraphNode {
+    pub id: u64,
+    pub title: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct LinkEdge {
+    pub source: u64,
+    pub target: LinkTarget,
+    /// The note the link points to; `None` when the link is broken.
+    pub resolved: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LinkGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<LinkEdge>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct LinkIndex {
+    by_source: HashMap<u64, Vec<LinkTarget>>,
+}
+
+/// Distinct `[[...]]` targets in `content`, in order of first appearance.
+/// A target that is all digits is a note id; anything else is a title.
+pub fn parse_links(content: &str) -> Vec<LinkTarget> {
+    let mut targets = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let text = rest[..end].trim();
+        if text.is_empty() || text.contains('\n') || text.chars().count() > MAX_LINK_TEXT_CHARS {
+            continue;
+        }
+        rest = &rest[end + 2..];
+        let target = match text.parse::<u64>() {
+            Ok(id) => LinkTarget::Id(id),
+            Err(_) => LinkTarget::Title(text.to_string()),
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+            if targets.len() == MAX_LINKS_PER_NOTE {
+                break;
+            }
+        }
+    }
+    targets
+}
+
+/// Key used to match `[[Title]]` links against note titles.
+pub fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+impl LinkIndex {
+    pub fn set(&mut self, source: u64, targets: Vec<LinkTarget>) {
+        if targets.is_empty() {
+            self.by_source.remove(&source);
+        } else {
+            self.by_source.insert(source, targets);
+        }
+    }
+
+    pub fn remove_source(&mut self, source: u64) {
+        self.by_source.remove(&source);
+    }
+
+    pub fn targets(&self, source: u64) -> &[LinkTarget] {
+        self.by_source.get(&source).map_or(&[], Vec::as_slice)
+    }
+
+    /// Every source note with at least one link.
+    pub fn sources(&self) -> impl Iterator<Item = u64> + '_ {
+        self.by_source.keys().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_links() {
+        let content = "See [[12]], [[ Project Plan ]] and [[12]].\n[[]] [[broken\nlink]] [[Tail";
+        assert_eq!(
+            parse_links(content),
+            vec![LinkTarget::Id(12), LinkTarget::Title("Project Plan".to_string())]
+        );
+        assert_eq!(parse_links("[[a]][[b]]").len(), 2);
+        assert_eq!(title_key("  Project PLAN "), "project plan");
+    }
+}
@@ -0,0 +1,315 @@
+// --- Markdown Documents with YAML Front Matter ---
+// Only the flat subset of YAML that notes need is supported: scalar strings
+// and lists of strings, either inline (`[a, b]`) or as `- item` blocks.
+
+/// A single front matter value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrontMatterValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl FrontMatterValue {
+    /// Returns the value as a list, treating a scalar as a single-item list.
+    pub fn into_list(self) -> Vec<String> {
+        match self {
+            FrontMatterValue::Text(text) if text.is_empty() => Vec::new(),
+            FrontMatterValue::Text(text) => vec![text],
+            FrontMatterValue::List(items) => items,
+        }
+    }
+}
+
+/// A Markdown document split into its front matter fields and body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Document {
+    pub fields: Vec<(String, FrontMatterValue)>,
+    pub body: String,
+}
+
+impl Document {
+    /// Removes and returns the field named `key`, if present.
+    pub fn take(&mut self, key: &str) -> Option<FrontMatterValue> {
+        let idx = self.fields.iter().position(|(k, _)| k == key)?;
+        Some(self.fields.remove(idx).1)
+    }
+}
+
+/// Renders `fields` as a `---` delimited front matter block followed by `body`.
+pub fn render_document(fields: &[(&str, FrontMatterValue)], body: &str) -> String {
+    let mut out = String::from("---\n");
+    for (key, value) in fields {
+        match value {
+            FrontMatterValue::Text(text) => {
+                out.push_str(&format!("{}: {}\n", key, quote_yaml(text)));
+            }
+            FrontMatterValue::List(items) => {
+                let quoted: Vec<String> = items.iter().map(|item| quote_yaml(item)).collect();
+                out.push_str(&format!("{}: [{}]\n", key, quoted.join(", ")));
+            }
+        }
+    }
+    out.push_str("---\n\n");
+    out.push_str(body);
+    if !body.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Splits `text` into front matter and body. Documents without a leading `---`
+/// line are returned with no fields and the whole text as body.
+pub fn parse_document(text: &str) -> Result<Document, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut lines = text.split_inclusive('\n');
+
+    let opening = lines.next().unwrap_or("");
+    if opening.trim_end() != "---" {
+        return Ok(Document {
+            fields: Vec::new(),
+            body: text.to_string(),
+        });
+    }
+
+    let mut fields: Vec<(String, FrontMatterValue)> = Vec::new();
+    let mut consumed = opening.len();
+    let mut closed = false;
+
+    for raw in lines.by_ref() {
+        consumed += raw.len();
+        let line = raw.trim_end_matches(['\r', '\n']);
+        if line.trim_end() == "---" || line.trim_end() == "..." {
+            closed = true;
+            break;
+        }
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            let item = parse_scalar(item)?;
+            match fields.last_mut() {
+                Some((_, FrontMatterValue::List(items))) => items.push(item),
+                // `key:` with an empty value opens a block list
+                Some((_, value)) if *value == FrontMatterValue::Text(String::new()) => {
+                    *value = FrontMatterValue::List(vec![item]);
+                }
+                _ => return Err(format!("Unexpected list item in front matter: {}", line)),
+            }
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid front matter line: {}", line))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("Invalid front matter key: {}", key));
+        }
+        let value = value.trim();
+        let value = if value.starts_with('[') {
+            parse_inline_list(value)?
+        } else {
+            FrontMatterValue::Text(parse_scalar(value)?)
+        };
+        fields.push((key.to_string(), value));
+    }
+
+    if !closed {
+        return Err("Front matter is missing its closing '---' line.".to_string());
+    }
+
+    let body = &text[consumed..];
+    let body = body.strip_prefix("\r\n").or_else(|| body.strip_prefix('\n')).unwrap_or(body);
+    Ok(Document {
+        fields,
+        body: body.to_string(),
+    })
+}
+
+fn parse_inline_list(value: &str) -> Result<FrontMatterValue, String> {
+    let inner = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .ok_or_else(|| format!("Unterminated list in front matter: {}", value))?;
+
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for ch in inner.chars() {
+        match quote {
+            Some(q) => {
+                current.push(ch);
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' && q == '"' {
+                    escaped = true;
+                } else if ch == q {
+                    quote = None;
+                }
+            }
+            None if ch == ',' => {
+                items.push(parse_scalar(&current)?);
+                current.clear();
+            }
+            None => {
+                if ch == '"' || ch == '\'' {
+                    quote = Some(ch);
+                }
+                current.push(ch);
+            }
+        }
+    }
+    if !current.trim().is_empty() || !items.is_empty() {
+        items.push(parse_scalar(&current)?);
+    }
+    Ok(FrontMatterValue::List(items))
+}
+
+fn parse_scalar(raw: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| format!("Unterminated string in front matter: {}", raw))?;
+        return unescape_yaml(inner);
+    }
+    if let Some(inner) = raw.strip_prefix('\'') {
+        let inner = inner
+            .strip_suffix('\'')
+            .ok_or_else(|| format!("Unterminated string in front matter: {}", raw))?;
+        return Ok(inner.replace("''", "'"));
+    }
+    // Unquoted scalars end at an inline comment.
+    let value = match raw.find(" #") {
+        Some(idx) => &raw[..idx],
+        None => raw,
+    };
+    Ok(value.trim_end().to_string())
+}
+
+fn unescape_yaml(inner: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('/') => out.push('/'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let decoded = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid unicode escape in front matter: \\u{}", hex))?;
+                out.push(decoded);
+            }
+            other => {
+                return Err(format!(
+                    "Invalid escape sequence in front matter: \\{}",
+                    other.map(String::from).unwrap_or_default()
+                ))
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Quotes a string as a double-quoted YAML scalar.
+pub fn quote_yaml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Turns a title into a file-name friendly slug.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for ch in title.chars() {
+        if ch.is_alphanumeric() {
+            slug.extend(ch.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 48 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    if slug.is_empty() {
+        "note".to_string()
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_document_quotes_values() {
+        let doc = render_document(
+            &[
+                ("title", FrontMatterValue::Text("Say \"hi\"".to_string())),
+                ("tags", FrontMatterValue::List(vec!["a".to_string(), "b c".to_string()])),
+            ],
+            "Body",
+        );
+        assert_eq!(doc, "---\ntitle: \"Say \\\"hi\\\"\"\ntags: [\"a\", \"b c\"]\n---\n\nBody\n");
+
+        let parsed = parse_document(&doc).unwrap();
+        assert_eq!(parsed.body, "Body\n");
+        assert_eq!(
+            parsed.fields,
+            vec![
+                ("title".to_string(), FrontMatterValue::Text("Say \"hi\"".to_string())),
+                ("tags".to_string(), FrontMatterValue::List(vec!["a".to_string(), "b c".to_string()])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_document_block_lists_and_plain_scalars() {
+        let text = "---\ntitle: Weekly sync # comment\ntags:\n  - work\n  - 'team''s'\n---\n# Agenda\n";
+        let mut doc = parse_document(text).unwrap();
+        assert_eq!(doc.take("title"), Some(FrontMatterValue::Text("Weekly sync".to_string())));
+        assert_eq!(
+            doc.take("tags").map(FrontMatterValue::into_list),
+            Some(vec!["work".to_string(), "team's".to_string()])
+        );
+        assert_eq!(doc.body, "# Agenda\n");
+
+        let plain = parse_document("Just text").unwrap();
+        assert!(plain.fields.is_empty());
+        assert_eq!(plain.body, "Just text");
+
+        assert!(parse_document("---\ntitle: x\n").is_err());
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Meeting Notes: 2024/01"), "meeting-notes-2024-01");
+        assert_eq!(slugify("!!!"), "note");
+    }
+}
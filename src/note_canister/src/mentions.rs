@@ -0,0 +1,99 @@
+// --- Mentions ---
+// `@<principal>` in a plaintext note's content mentions that principal. The
+// canister records who each note mentions so mentioned principals can be
+// notified once and can list the notes that mention them.
+
+use std::collections::{BTreeSet, HashMap};
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+/// Mentions beyond this many distinct principals in one note are ignored.
+pub const MAX_MENTIONS_PER_NOTE: usize = 20;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct MentionIndex {
+    by_note: HashMap<u64, BTreeSet<Principal>>,
+}
+
+/// Principals mentioned in `content`, in order of first appearance. An `@`
+/// only starts a mention at the start of a word, so e-mail addresses are skipped.
+pub fn parse_mentions(content: &str) -> Vec<Principal> {
+    let mut mentioned = Vec::new();
+    let mut previous = None;
+    for (index, c) in content.char_indices() {
+        let starts_word = previous.is_none_or(|p: char| !p.is_alphanumeric());
+        previous = Some(c);
+        if c != '@' || !starts_word {
+            continue;
+        }
+        let rest = &content[index + 1..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'))
+            .unwrap_or(rest.len());
+        let Ok(principal) = Principal::from_text(rest[..end].trim_end_matches('-')) else {
+            continue;
+        };
+        if !mentioned.contains(&principal) {
+            mentioned.push(principal);
+            if mentioned.len() == MAX_MENTIONS_PER_NOTE {
+                break;
+            }
+        }
+    }
+    mentioned
+}
+
+impl MentionIndex {
+    /// Replaces the mentions recorded for `note_id` and returns the principals
+    /// that were not mentioned before.
+    pub fn set(&mut self, note_id: u64, mentioned: Vec<Principal>) -> Vec<Principal> {
+        let previous = self.by_note.remove(&note_id).unwrap_or_default();
+        let added = mentioned.iter().filter(|principal| !previous.contains(principal)).copied().collect();
+        if !mentioned.is_empty() {
+            self.by_note.insert(note_id, mentioned.into_iter().collect());
+        }
+        added
+    }
+
+    pub fn remove_note(&mut self, note_id: u64) {
+        self.by_note.remove(&note_id);
+    }
+
+    /// Ids of the notes that mention `principal`, ascending.
+    pub fn notes_mentioning(&self, principal: Principal) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .by_note
+            .iter()
+            .filter(|(_, mentioned)| mentioned.contains(&principal))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_index_mentions() {
+        let alice = Principal::from_slice(&[1; 29]);
+        let bob = Principal::from_slice(&[2; 29]);
+        let content = format!("cc @{}, @{}. mail me@{} or @not-a-principal @{}", alice, bob, bob, alice);
+        assert_eq!(parse_mentions(&content), vec![alice, bob]);
+        assert_eq!(parse_mentions(&format!("@{}-", alice)), vec![alice]);
+
+        let mut index = MentionIndex::default();
+        assert_eq!(index.set(1, vec![alice]), vec![alice]);
+        assert_eq!(index.set(1, vec![alice, bob]), vec![bob]);
+        assert_eq!(index.set(2, vec![bob]), vec![bob]);
+        assert_eq!(index.notes_mentioning(bob), vec![1, 2]);
+
+        index.set(1, Vec::new());
+        index.remove_note(2);
+        assert!(index.notes_mentioning(bob).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+// --- Per-Viewer Note State ---
+// Pins, favorites, archiving and color labels belong to the principal who set
+// them, not to the note, so each recipient of a shared note organizes it
+// independently of the owner.
+
+use std::collections::BTreeMap;
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+pub const MAX_STATES_PER_VIEWER: usize = 5_000;
+
+// --- Data Structures ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub enum NoteColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Pink,
+    Gray,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct NoteState {
+    /// Sorted ahead of other notes in listings.
+    pub pinned: bool,
+    pub favorite: bool,
+    /// Left out of default listings.
+    pub archived: bool,
+    pub color: Option<NoteColor>,
+}
+
+/// Entry of `get_my_note_states`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct NoteStateEntry {
+    pub note_id: u64,
+    pub state: NoteState,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct NoteStates {
+    by_viewer: BTreeMap<Principal, BTreeMap<u64, NoteState>>,
+}
+
+impl NoteStates {
+    pub fn get(&self, viewer: Principal, note_id: u64) -> NoteState {
+        self.by_viewer
+            .get(&viewer)
+            .and_then(|states| states.get(&note_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies `change` to `viewer`'s state for `note_id`. States back at the
+    /// default are not stored.
+    pub fn update(&mut self, viewer: Principal, note_id: u64, change: impl FnOnce(&mut NoteState)) -> Result<(), String> {
+        let mut state = self.get(viewer, note_id);
+        change(&mut state);
+        let states = self.by_viewer.entry(viewer).or_default();
+        if state == NoteState::default() {
+            states.remove(&note_id);
+            if states.is_empty() {
+                self.by_viewer.remove(&viewer);
+            }
+        } else if states.contains_key(&note_id) || states.len() < MAX_STATES_PER_VIEWER {
+            states.insert(note_id, state);
+        } else {
+            return Err(format!(
+                "You can pin, favorite, archive or color at most {} notes.",
+                MAX_STATES_PER_VIEWER
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn of_viewer(&self, viewer: Principal) -> Vec<NoteStateEntry> {
+        self.by_viewer
+            .get(&viewer)
+            .into_iter()
+            .flatten()
+            .map(|(note_id, state)| NoteStateEntry {
+                note_id: *note_id,
+                state: *state,
+            })
+            .collect()
+    }
+
+    pub fn remove_note(&mut self, note_id: u64) {
+        self.by_viewer.retain(|_, states| {
+            states.remove(&note_id);
+            !states.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_states_are_per_viewer_and_sparse() {
+        let alice = Principal::from_slice(&[1; 29]);
+        let bob = Principal::from_slice(&[2; 29]);
+        let mut states = NoteStates::default();
+
+        states.update(alice, 1, |state| state.pinned = true).unwrap();
+        states.update(bob, 1, |state| state.color = Some(NoteColor::Blue)).unwrap();
+        assert!(states.get(alice, 1).pinned);
+        assert!(!states.get(bob, 1).pinned);
+
+        states.update(alice, 1, |state| state.pinned = false).unwrap();
+        assert!(states.of_viewer(alice).is_empty());
+        states.remove_note(1);
+        assert_eq!(states, NoteStates::default());
+
+        for note_id in 0..MAX_STATES_PER_VIEWER as u64 {
+            states.update(alice, note_id, |state| state.favorite = true).unwrap();
+        }
+        assert!(states.update(alice, u64::MAX, |state| state.favorite = true).is_err());
+        assert!(states.update(alice, 0, |state| state.pinned = true).is_ok());
+    }
+}
@@ -0,0 +1,288 @@
+// --- Notion Export Import ---
+// Notion's "Markdown & CSV" export is a ZIP (sometimes wrapping further ZIPs)
+// of `<Title> <32-hex-id>.md` pages and `<Database> <id>.csv` tables. Database
+// rows are also written as pages, so a CSV row only becomes a note when no
+// page with the same title exists.
+
+use std::collections::HashSet;
+
+use crate::csv;
+use crate::datetime::parse_rfc3339;
+use crate::import::NoteDraft;
+use crate::zip::{read_archive, ZipEntry};
+
+const TAG_KEYS: [&str; 2] = ["tags", "tag"];
+const CREATED_KEYS: [&str; 3] = ["created", "created time", "date created"];
+const UPDATED_KEYS: [&str; 3] = ["last edited time", "last edited", "updated"];
+
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october",
+    "november", "december",
+];
+
+/// Parses a Notion export archive into drafts, pages first and then any
+/// database rows that have no page of their own.
+pub fn parse_notion_export(zip_bytes: &[u8], max_total_size: usize) -> Result<Vec<NoteDraft>, String> {
+    // Nested archives draw from the same budget as the outer one.
+    let mut budget = max_total_size;
+    let mut files = Vec::new();
+    for entry in read_archive(zip_bytes, &mut budget)? {
+        if entry.name.to_ascii_lowercase().ends_with(".zip") {
+            files.extend(read_archive(&entry.data, &mut budget)?);
+        } else {
+            files.push(entry);
+        }
+    }
+
+    let names: HashSet<String> = files.iter().map(|entry| entry.name.clone()).collect();
+    let mut drafts = Vec::new();
+    let mut page_titles = HashSet::new();
+    let mut tables: Vec<&ZipEntry> = Vec::new();
+
+    for entry in &files {
+        let lower = entry.name.to_ascii_lowercase();
+        if lower.ends_with(".md") {
+            let text = String::from_utf8_lossy(&entry.data);
+            let draft = parse_page(&entry.name, &text);
+            page_titles.insert(draft.title.clone());
+            drafts.push(draft);
+        } else if lower.ends_with(".csv") {
+            // Newer exports add `<Database>_all.csv` next to the filtered view.
+            let is_duplicate_view = entry
+                .name
+                .strip_suffix("_all.csv")
+                .is_some_and(|base| names.contains(&format!("{}.csv", base)));
+            if !is_duplicate_view {
+                tables.push(entry);
+            }
+        }
+    }
+
+    for table in tables {
+        let text = String::from_utf8_lossy(&table.data);
+        for draft in parse_table(&text)? {
+            if !page_titles.contains(&draft.title) {
+                drafts.push(draft);
+            }
+        }
+    }
+
+    Ok(drafts)
+}
+
+fn parse_page(path: &str, text: &str) -> NoteDraft {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut lines = text.lines().peekable();
+
+    let title = match lines.peek().and_then(|line| line.strip_prefix("# ")) {
+        Some(heading) => {
+            let heading = heading.trim().to_string();
+            lines.next();
+            heading
+        }
+        None => title_from_path(path),
+    };
+    while lines.peek().is_some_and(|line| line.trim().is_empty()) {
+        lines.next();
+    }
+
+    // Database pages list their properties as `Key: Value` lines under the title.
+    let mut draft = NoteDraft {
+        title,
+        content: String::new(),
+        tags: Vec::new(),
+        created_at: None,
+        updated_at: None,
+    };
+    let mut kept_properties = Vec::new();
+    while let Some((key, value)) = lines.peek().and_then(|line| property_line(line)) {
+        if !apply_property(&mut draft, key, value) {
+            kept_properties.push(format!("{}: {}", key, value));
+        }
+        lines.next();
+    }
+
+    let body: Vec<&str> = lines.collect();
+    let mut content = kept_properties.join("\n");
+    if !content.is_empty() && !body.is_empty() {
+        content.push_str("\n\n");
+    }
+    content.push_str(body.join("\n").trim());
+    draft.content = content.trim().to_string();
+    draft
+}
+
+fn parse_table(text: &str) -> Result<Vec<NoteDraft>, String> {
+    let mut records = csv::parse(text)?.into_iter();
+    let Some(header) = records.next() else {
+        return Ok(Vec::new());
+    };
+
+    let mut drafts = Vec::new();
+    for record in records {
+        let Some(title) = record.first().map(|title| title.trim().to_string()) else {
+            continue;
+        };
+        if title.is_empty() {
+            continue;
+        }
+        let mut draft = NoteDraft {
+            title,
+            content: String::new(),
+            tags: Vec::new(),
+            created_at: None,
+            updated_at: None,
+        };
+        let mut lines = Vec::new();
+        for (key, value) in header.iter().zip(record.iter()).skip(1) {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            if !apply_property(&mut draft, key.trim(), value) {
+                lines.push(format!("{}: {}", key.trim(), value));
+            }
+        }
+        draft.content = lines.join("\n");
+        drafts.push(draft);
+    }
+    Ok(drafts)
+}
+
+/// Consumes tags and timestamps into `draft`; returns `false` for other keys,
+/// and for dates in a format it cannot read, so that they are kept as text.
+fn apply_property(draft: &mut NoteDraft, key: &str, value: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    let timestamp = if TAG_KEYS.contains(&key.as_str()) {
+        draft.tags = value.split(',').map(|tag| tag.trim().to_string()).collect();
+        return true;
+    } else if CREATED_KEYS.contains(&key.as_str()) {
+        &mut draft.created_at
+    } else if UPDATED_KEYS.contains(&key.as_str()) {
+        &mut draft.updated_at
+    } else {
+        return false;
+    };
+    match parse_notion_date(value) {
+        Ok(nanos) => {
+            *timestamp = Some(nanos);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn property_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(": ")?;
+    let key_ok = !key.is_empty()
+        && key.len() <= 40
+        && key.starts_with(|c: char| c.is_alphabetic())
+        && key.chars().all(|c| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_');
+    (key_ok && !value.trim().is_empty()).then_some((key, value.trim()))
+}
+
+/// Strips the directory, extension and trailing 32-hex-digit page id.
+fn title_from_path(path: &str) -> String {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = file.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file);
+    match stem.rsplit_once(' ') {
+        Some((title, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => title.to_string(),
+        _ => stem.to_string(),
+    }
+}
+
+/// Notion writes dates as `January 31, 2024 9:30 AM`, with optional time.
+fn parse_notion_date(value: &str) -> Result<u64, String> {
+    if let Some(nanos) = parse_rfc3339(value) {
+        return Ok(nanos);
+    }
+    parse_long_date(value).ok_or_else(|| format!("Invalid Notion date: {}", value))
+}
+
+fn parse_long_date(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let month_name = parts.next()?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| *m == month_name)? + 1;
+    let day: u32 = parts.next()?.trim_end_matches(',').parse().ok()?;
+    let year: u32 = parts.next()?.parse().ok()?;
+
+    let (mut hour, mut minute) = (0u32, 0u32);
+    if let Some(clock) = parts.next() {
+        let (h, m) = clock.split_once(':')?;
+        hour = h.parse().ok()?;
+        minute = m.parse().ok()?;
+        match parts.next().map(|s| s.to_ascii_uppercase()).as_deref() {
+            Some("PM") if hour < 12 => hour += 12,
+            Some("AM") if hour == 12 => hour = 0,
+            Some("AM") | Some("PM") | None => {}
+            Some(_) => return None,
+        }
+    }
+
+    parse_rfc3339(&format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:00Z",
+        year, month, day, hour, minute
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zip::tests::build_stored_archive;
+
+    #[test]
+    fn test_parse_notion_export() {
+        let page = "# Roadmap\n\nTags: planning, q3\nCreated: January 31, 2024 9:30 PM\nStatus: Draft\n\nShip the thing.\n";
+        let table = "Name,Tags,Owner\r\nRoadmap,planning,Ann\r\nBacklog,,Bo\r\n";
+        let inner = build_stored_archive(&[
+            ("Roadmap 0123456789abcdef0123456789abcdef.md", page.as_bytes()),
+            ("Projects 0123456789abcdef0123456789abcdef.csv", table.as_bytes()),
+            ("Projects 0123456789abcdef0123456789abcdef_all.csv", table.as_bytes()),
+            ("Untitled 0123456789abcdef0123456789abcdef.md", b"Loose text"),
+        ]);
+        let outer = build_stored_archive(&[("Export-1234-Part-1.zip", &inner)]);
+
+        let drafts = parse_notion_export(&outer, 1 << 20).unwrap();
+        assert_eq!(drafts.len(), 3);
+        // Either archive alone fits, but the nested one shares the outer budget.
+        assert!(parse_notion_export(&outer, inner.len() + 10).is_err());
+
+        assert_eq!(drafts[0].title, "Roadmap");
+        assert_eq!(drafts[0].tags, vec!["planning".to_string(), "q3".to_string()]);
+        assert_eq!(drafts[0].created_at, parse_rfc3339("2024-01-31T21:30:00Z"));
+        assert_eq!(drafts[0].content, "Status: Draft\n\nShip the thing.");
+
+        assert_eq!(drafts[1].title, "Untitled");
+        assert_eq!(drafts[1].content, "Loose text");
+
+        assert_eq!(drafts[2].title, "Backlog");
+        assert_eq!(drafts[2].content, "Owner: Bo");
+    }
+
+    #[test]
+    fn test_unreadable_dates_are_kept_as_text() {
+        let page = "# Trip\n\nCreated: 31/01/2024 21:30\n\nPack bags.\n";
+        let table = "Name,Created\r\nBudget,\"January 31, 2024\"\r\nVisa,31.01.2024\r\n";
+        let archive = build_stored_archive(&[
+            ("Trip 0123456789abcdef0123456789abcdef.md", page.as_bytes()),
+            ("Plans 0123456789abcdef0123456789abcdef.csv", table.as_bytes()),
+        ]);
+
+        let drafts = parse_notion_export(&archive, 1 << 20).unwrap();
+        assert_eq!(drafts.len(), 3);
+        assert_eq!(drafts[0].created_at, None);
+        assert_eq!(drafts[0].content, "Created: 31/01/2024 21:30\n\nPack bags.");
+        assert_eq!(drafts[1].created_at, parse_rfc3339("2024-01-31"));
+        assert_eq!((drafts[2].created_at, drafts[2].content.as_str()), (None, "Created: 31.01.2024"));
+    }
+
+    #[test]
+    fn test_parse_notion_date() {
+        assert_eq!(parse_notion_date("March 3, 2023"), Ok(parse_rfc3339("2023-03-03").unwrap()));
+        assert_eq!(
+            parse_notion_date("March 3, 2023 12:05 AM"),
+            Ok(parse_rfc3339("2023-03-03T00:05:00Z").unwrap())
+        );
+        assert!(parse_notion_date("sometime").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// --- Typed Properties ---
+// Notes can carry a small map of typed values (status, priority, due date...).
+// An owner may declare a schema fixing the type of each key; once declared,
+// every property on that owner's notes must match it.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use candid::{CandidType, Deserialize, Principal};
+
+// --- Constants ---
+pub const MAX_PROPERTIES_PER_NOTE: usize = 50;
+const MAX_KEY_CHARS: usize = 64;
+const MAX_TEXT_VALUE_BYTES: usize = 512;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize, serde::Serialize)]
+pub enum PropertyValue {
+    Text(String),
+    Number(f64),
+    /// Nanoseconds since the epoch.
+    Date(u64),
+    Bool(bool),
+    Principal(Principal),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, CandidType, Deserialize, serde::Serialize)]
+pub enum PropertyKind {
+    Text,
+    Number,
+    Date,
+    Bool,
+    Principal,
+}
+
+pub type Properties = BTreeMap<String, PropertyValue>;
+/// Declared type of each allowed key.
+pub type PropertySchema = BTreeMap<String, PropertyKind>;
+
+/// Which values of a property a listing keeps.
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub enum PropertyCondition {
+    /// The note has the property, whatever its value.
+    Exists,
+    Equals(PropertyValue),
+    /// Values of the same type within the bounds, both inclusive.
+    Between {
+        min: Option<PropertyValue>,
+        max: Option<PropertyValue>,
+    },
+}
+
+impl PropertyValue {
+    pub fn kind(&self) -> PropertyKind {
+        match self {
+            PropertyValue::Text(_) => PropertyKind::Text,
+            PropertyValue::Number(_) => PropertyKind::Number,
+            PropertyValue::Date(_) => PropertyKind::Date,
+            PropertyValue::Bool(_) => PropertyKind::Bool,
+            PropertyValue::Principal(_) => PropertyKind::Principal,
+        }
+    }
+
+    /// Bytes charged against the owner's quota.
+    pub fn stored_bytes(&self) -> u64 {
+        match self {
+            PropertyValue::Text(text) => text.len() as u64,
+            PropertyValue::Principal(principal) => principal.as_slice().len() as u64,
+            PropertyValue::Number(_) | PropertyValue::Date(_) => 8,
+            PropertyValue::Bool(_) => 1,
+        }
+    }
+}
+
+/// Orders values of the same type; values of different types do not compare.
+pub fn compare(a: &PropertyValue, b: &PropertyValue) -> Option<Ordering> {
+    match (a, b) {
+        (PropertyValue::Text(a), PropertyValue::Text(b)) => Some(a.cmp(b)),
+        (PropertyValue::Number(a), PropertyValue::Number(b)) => a.partial_cmp(b),
+        (PropertyValue::Date(a), PropertyValue::Date(b)) => Some(a.cmp(b)),
+        (PropertyValue::Bool(a), PropertyValue::Bool(b)) => Some(a.cmp(b)),
+        (PropertyValue::Principal(a), PropertyValue::Principal(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Total order for sorting listings: values of one type by value, mixed types
+/// grouped in declaration order of `PropertyKind`.
+pub fn order(a: &PropertyValue, b: &PropertyValue) -> Ordering {
+    compare(a, b).unwrap_or_else(|| a.kind().cmp(&b.kind()))
+}
+
+impl PropertyCondition {
+    pub fn matches(&self, value: &PropertyValue) -> bool {
+        match self {
+            PropertyCondition::Exists => true,
+            PropertyCondition::Equals(expected) => compare(value, expected) == Some(Ordering::Equal),
+            PropertyCondition::Between { min, max } => {
+                min.as_ref().is_none_or(|min| compare(value, min).is_some_and(Ordering::is_ge))
+                    && max.as_ref().is_none_or(|max| compare(value, max).is_some_and(Ordering::is_le))
+            }
+        }
+    }
+}
+
+pub fn properties_bytes(properties: &Properties) -> u64 {
+    properties.iter().map(|(key, value)| key.len() as u64 + value.stored_bytes()).sum()
+}
+
+pub fn validate_key(key: &str) -> Result<(), String> {
+    let valid = !key.is_empty()
+        && key.chars().count() <= MAX_KEY_CHARS
+        && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!(
+            "Invalid property key '{}': use up to {} lowercase letters, digits, '_' or '-'.",
+            key, MAX_KEY_CHARS
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_schema(schema: &PropertySchema) -> Result<(), String> {
+    if schema.len() > MAX_PROPERTIES_PER_NOTE {
+        return Err(format!("A schema can declare at most {} properties.", MAX_PROPERTIES_PER_NOTE));
+    }
+    schema.keys().try_for_each(|key| validate_key(key))
+}
+
+/// Checks one property against the limits and, if the owner has one, their schema.
+pub fn validate_property(key: &str, value: &PropertyValue, schema: Option<&PropertySchema>) -> Result<(), String> {
+    validate_key(key)?;
+    match value {
+        PropertyValue::Text(text) if text.len() > MAX_TEXT_VALUE_BYTES => {
+            return Err(format!("Property '{}' exceeds {} byte limit.", key, MAX_TEXT_VALUE_BYTES));
+        }
+        PropertyValue::Number(number) if !number.is_finite() => {
+            return Err(format!("Property '{}' must be a finite number.", key));
+        }
+        _ => {}
+    }
+    if let Some(schema) = schema {
+        match schema.get(key) {
+            None => return Err(format!("Property '{}' is not declared in your property schema.", key)),
+            Some(kind) if *kind != value.kind() => {
+                return Err(format!("Property '{}' must be of type {:?}.", key, kind));
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_against_schema() {
+        let schema = PropertySchema::from([("priority".to_string(), PropertyKind::Number)]);
+        assert!(validate_property("priority", &PropertyValue::Number(2.0), Some(&schema)).is_ok());
+        assert_eq!(
+            validate_property("priority", &PropertyValue::Text("high".to_string()), Some(&schema)).unwrap_err(),
+            "Property 'priority' must be of type Number."
+        );
+        assert!(validate_property("status", &PropertyValue::Bool(true), Some(&schema)).is_err());
+        assert!(validate_property("status", &PropertyValue::Bool(true), None).is_ok());
+        assert!(validate_property("Status", &PropertyValue::Bool(true), None).is_err());
+        assert!(validate_property("score", &PropertyValue::Number(f64::NAN), None).is_err());
+    }
+
+    #[test]
+    fn test_conditions_compare_same_kind_only() {
+        let range = PropertyCondition::Between {
+            min: Some(PropertyValue::Number(1.0)),
+            max: Some(PropertyValue::Number(3.0)),
+        };
+        assert!(range.matches(&PropertyValue::Number(3.0)));
+        assert!(!range.matches(&PropertyValue::Number(3.5)));
+        assert!(!range.matches(&PropertyValue::Text("2".to_string())));
+        assert!(PropertyCondition::Equals(PropertyValue::Date(5)).matches(&PropertyValue::Date(5)));
+        assert!(PropertyCondition::Exists.matches(&PropertyValue::Bool(false)));
+    }
+}
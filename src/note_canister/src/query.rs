@@ -0,0 +1,223 @@
+// --- Note Queries ---
+// `query_notes` evaluates a filter expression over the notes a caller can list:
+// their own notes and encrypted notes shared with them. Encrypted notes have
+// no plaintext title or content here, so text terms never match them.
+
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::note_state::NoteState;
+use crate::properties::{self, PropertyCondition};
+use crate::Note;
+
+// --- Constants ---
+const DEFAULT_PAGE: usize = 50;
+const MAX_PAGE: usize = 100;
+const MAX_FILTER_DEPTH: usize = 8;
+const MAX_FILTER_TERMS: usize = 64;
+const MAX_TEXT_TERM_CHARS: usize = 200;
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub enum NoteFilter {
+    /// `created_at` within the bounds, both inclusive.
+    CreatedBetween { from: Option<u64>, to: Option<u64> },
+    /// Time of the last edit, or of creation for notes never edited.
+    UpdatedBetween { from: Option<u64>, to: Option<u64> },
+    Tag(String),
+    Owner(Principal),
+    /// Case-insensitive substring of the title or content.
+    Text(String),
+    /// The caller's own archive flag.
+    Archived(bool),
+    Property { key: String, condition: PropertyCondition },
+    /// Every term matches; an empty list matches everything.
+    And(Vec<NoteFilter>),
+    /// Any term matches; an empty list matches nothing.
+    Or(Vec<NoteFilter>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum NoteSortField {
+    CreatedAt,
+    UpdatedAt,
+    /// Case-insensitive.
+    Title,
+    /// Notes without the property come last in either direction.
+    Property(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct NoteSort {
+    pub field: NoteSortField,
+    pub descending: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, CandidType, Deserialize)]
+pub struct NoteQuery {
+    /// `None` matches every listable note, archived ones included.
+    pub filter: Option<NoteFilter>,
+    /// Defaults to ascending id.
+    pub sort: Option<NoteSort>,
+    /// Position to continue from, as returned in `next_cursor`.
+    pub cursor: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct NoteQueryPage {
+    pub notes: Vec<Note>,
+    /// Pass as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<u64>,
+    /// Matching notes across all pages.
+    pub total: u64,
+}
+
+/// A note being evaluated for one viewer. Content is loaded at most once, and
+/// only if a text term needs it.
+pub struct Candidate<'a> {
+    pub note: &'a Note,
+    pub state: NoteState,
+    content: OnceCell<String>,
+    load_content: fn(&Note) -> String,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new(note: &'a Note, state: NoteState, load_content: fn(&Note) -> String) -> Self {
+        Candidate {
+            note,
+            state,
+            content: OnceCell::new(),
+            load_content,
+        }
+    }
+
+    fn content(&self) -> &str {
+        self.content.get_or_init(|| (self.load_content)(self.note))
+    }
+}
+
+fn last_changed(note: &Note) -> u64 {
+    note.updated_at.unwrap_or(note.created_at)
+}
+
+fn within(value: u64, from: Option<u64>, to: Option<u64>) -> bool {
+    from.is_none_or(|from| value >= from) && to.is_none_or(|to| value <= to)
+}
+
+impl NoteFilter {
+    /// Rejects filters that are too deep or too large to evaluate in one query.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut terms = 0;
+        self.validate_at(1, &mut terms)
+    }
+
+    fn validate_at(&self, depth: usize, terms: &mut usize) -> Result<(), String> {
+        if depth > MAX_FILTER_DEPTH {
+            return Err(format!("Filters can be nested at most {} levels deep.", MAX_FILTER_DEPTH));
+        }
+        *terms += 1;
+        if *terms > MAX_FILTER_TERMS {
+            return Err(format!("A filter can have at most {} terms.", MAX_FILTER_TERMS));
+        }
+        match self {
+            NoteFilter::Text(text) if text.trim().is_empty() || text.chars().count() > MAX_TEXT_TERM_CHARS => Err(
+                format!("Text filters must have between 1 and {} characters.", MAX_TEXT_TERM_CHARS),
+            ),
+            NoteFilter::Property { key, .. } => properties::validate_key(key),
+            NoteFilter::And(filters) | NoteFilter::Or(filters) => {
+                filters.iter().try_for_each(|filter| filter.validate_at(depth + 1, terms))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, candidate: &Candidate) -> bool {
+        let note = candidate.note;
+        match self {
+            NoteFilter::CreatedBetween { from, to } => within(note.created_at, *from, *to),
+            NoteFilter::UpdatedBetween { from, to } => within(last_changed(note), *from, *to),
+            NoteFilter::Tag(tag) => note.tags.iter().any(|t| t == tag.trim()),
+            NoteFilter::Owner(owner) => note.owner == *owner,
+            NoteFilter::Text(text) => {
+                let needle = text.trim().to_lowercase();
+                note.title.to_lowercase().contains(&needle) || candidate.content().to_lowercase().contains(&needle)
+            }
+            NoteFilter::Archived(archived) => candidate.state.archived == *archived,
+            NoteFilter::Property { key, condition } => note
+                .properties
+                .as_ref()
+                .and_then(|properties| properties.get(key))
+                .is_some_and(|value| condition.matches(value)),
+            NoteFilter::And(filters) => filters.iter().all(|filter| filter.matches(candidate)),
+            NoteFilter::Or(filters) => filters.iter().any(|filter| filter.matches(candidate)),
+        }
+    }
+}
+
+fn compare_by(field: &NoteSortField, a: &Note, b: &Note, descending: bool) -> Ordering {
+    let ordering = match field {
+        NoteSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+        NoteSortField::UpdatedAt => last_changed(a).cmp(&last_changed(b)),
+        NoteSortField::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        NoteSortField::Property(key) => {
+            let value = |note: &Note| note.properties.as_ref().and_then(|properties| properties.get(key).cloned());
+            return match (value(a), value(b)) {
+                (Some(a), Some(b)) if descending => properties::order(&b, &a),
+                (Some(a), Some(b)) => properties::order(&a, &b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            };
+        }
+    };
+    if descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// Sorts matching notes by `sort`, ties by ascending id.
+pub fn sort_notes(notes: &mut [&Note], sort: Option<&NoteSort>) {
+    notes.sort_by(|a, b| {
+        sort.map_or(Ordering::Equal, |sort| compare_by(&sort.field, a, b, sort.descending))
+            .then(a.id.cmp(&b.id))
+    });
+}
+
+/// The `(start, end)` slice of `total` sorted matches to return, and the cursor
+/// for the page after it.
+pub fn page_bounds(total: usize, cursor: Option<u64>, limit: Option<u32>) -> (usize, usize, Option<u64>) {
+    let limit = limit.map_or(DEFAULT_PAGE, |l| (l as usize).clamp(1, MAX_PAGE));
+    let start = cursor.map_or(0, |cursor| (cursor as usize).min(total));
+    let end = (start + limit).min(total);
+    (start, end, (end < total).then_some(end as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_validation_limits() {
+        let mut filter = NoteFilter::Tag("work".to_string());
+        for _ in 0..MAX_FILTER_DEPTH - 1 {
+            filter = NoteFilter::And(vec![filter]);
+        }
+        assert!(filter.validate().is_ok());
+        assert!(NoteFilter::Or(vec![filter]).validate().is_err());
+
+        let wide = NoteFilter::Or(vec![NoteFilter::Archived(false); MAX_FILTER_TERMS]);
+        assert!(wide.validate().is_err());
+        assert!(NoteFilter::Text("  ".to_string()).validate().is_err());
+    }
+
+    #[test]
+    fn test_page_bounds() {
+        assert_eq!(page_bounds(5, None, Some(2)), (0, 2, Some(2)));
+        assert_eq!(page_bounds(5, Some(4), Some(2)), (4, 5, None));
+        assert_eq!(page_bounds(5, Some(9), None), (5, 5, None));
+        assert_eq!(page_bounds(500, None, Some(1000)), (0, MAX_PAGE, Some(MAX_PAGE as u64)));
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::batch::BatchOp;
+use crate::checklist::Checklist;
+use crate::properties;
+use crate::config::Quota;
+use crate::{Note, NoteStore};
+
+// --- Data Structures ---
+/// What one principal currently stores.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CandidType, Deserialize)]
+pub struct Usage {
+    pub notes: u64,
+    pub bytes: u64,
+}
+
+/// Response of `get_my_usage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct UsageReport {
+    pub usage: Usage,
+    pub quota: Quota,
+}
+
+/// Signed change to a principal's usage caused by one write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsageDelta {
+    pub notes: i64,
+    pub bytes: i64,
+}
+
+pub fn content_bytes(title: &str, content: &str, tags: &[String]) -> u64 {
+    (title.len() + content.len() + tags.iter().map(String::len).sum::<usize>()) as u64
+}
+
+/// Bytes charged for a stored note. Shared content is charged to every owner.
+pub fn note_bytes(note: &Note) -> u64 {
+    let encrypted = note.encrypted.as_ref().map_or(0, |encrypted| encrypted.stored_bytes());
+    content_bytes(&note.title, "", &[]) + crate::content_len(note) as u64 + encrypted + kept_bytes(note)
+}
+
+/// Bytes that replacing a note's title and content leaves alone: its tags,
+/// checklist items and properties. Every size computation goes through this.
+fn kept_bytes(note: &Note) -> u64 {
+    content_bytes("", "", &note.tags)
+        + note.checklist.as_ref().map_or(0, Checklist::bytes)
+        + note.properties.as_ref().map_or(0, properties::properties_bytes)
+}
+
+pub fn usage_of(notes: &NoteStore, owner: Principal) -> Usage {
+    notes
+        .values()
+        .filter(|note| note.owner == owner)
+        .fold(Usage::default(), |usage, note| Usage {
+            notes: usage.notes + 1,
+            bytes: usage.bytes + note_bytes(note),
+        })
+}
+
+impl UsageDelta {
+    pub fn create(bytes: u64) -> Self {
+        UsageDelta {
+            notes: 1,
+            bytes: bytes as i64,
+        }
+    }
+
+    /// Replacing `note`'s title and content, keeping its tags, checklist items and properties.
+    pub fn update(note: &Note, title: &str, content: &str) -> Self {
+        UsageDelta {
+            notes: 0,
+            bytes: (content_bytes(title, content, &[]) + kept_bytes(note)) as i64 - note_bytes(note) as i64,
+        }
+    }
+
+    /// Growing or shrinking a note by `bytes`.
+    pub fn resize(bytes: i64) -> Self {
+        UsageDelta { notes: 0, bytes }
+    }
+}
+
+/// Applies `delta` to `usage`, failing only when the write grows usage past
+/// the quota. Writes that shrink usage are always allowed, even over quota.
+pub fn check_quota(usage: Usage, quota: Quota, delta: UsageDelta) -> Result<Usage, String> {
+    let notes = usage.notes.saturating_add_signed(delta.notes);
+    let bytes = usage.bytes.saturating_add_signed(delta.bytes);
+    if delta.notes > 0 && notes > quota.max_notes {
+        return Err(format!("Quota exceeded: you can store at most {} notes.", quota.max_notes));
+    }
+    if delta.bytes > 0 && bytes > quota.max_bytes {
+        return Err(format!(
+            "Quota exceeded: your notes can use at most {} bytes. This change needs {} more.",
+            quota.max_bytes,
+            bytes - quota.max_bytes
+        ));
+    }
+    Ok(Usage { notes, bytes })
+}
+
+/// Usage change of each operation in a validated batch, in order.
+pub fn batch_deltas(notes: &NoteStore, ops: &[BatchOp]) -> Vec<UsageDelta> {
+    // (total bytes, bytes an update keeps) of notes already touched earlier in the batch.
+    let mut sizes: HashMap<u64, (u64, u64)> = HashMap::new();
+    let current = |sizes: &mut HashMap<u64, (u64, u64)>, id: u64| {
+        *sizes.entry(id).or_insert_with(|| {
+            notes
+                .get(&id)
+                .map(|note| (note_bytes(note), kept_bytes(note)))
+                .unwrap_or_default()
+        })
+    };
+
+    let mut deltas = Vec::with_capacity(ops.len());
+    for op in ops {
+        let delta = match op {
+            BatchOp::Create { title, content } => UsageDelta::create(content_bytes(title, content, &[])),
+            BatchOp::Update { id, title, content } => {
+                let (old_bytes, kept_bytes) = current(&mut sizes, *id);
+                let new_bytes = content_bytes(title, content, &[]) + kept_bytes;
+                sizes.insert(*id, (new_bytes, kept_bytes));
+                UsageDelta {
+                    notes: 0,
+                    bytes: new_bytes as i64 - old_bytes as i64,
+                }
+            }
+            BatchOp::Delete { id } => {
+                let (old_bytes, _) = current(&mut sizes, *id);
+                UsageDelta {
+                    notes: -1,
+                    bytes: -(old_bytes as i64),
+                }
+            }
+        };
+        deltas.push(delta);
+    }
+    deltas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_quota_blocks_growth_only() {
+        let quota = Quota {
+            max_notes: 2,
+            max_bytes: 10,
+        };
+        let full = Usage { notes: 2, bytes: 12 };
+
+        assert_eq!(
+            check_quota(full, quota, UsageDelta::create(1)).unwrap_err(),
+            "Quota exceeded: you can store at most 2 notes."
+        );
+        assert!(check_quota(full, quota, UsageDelta { notes: 0, bytes: 1 }).is_err());
+        assert_eq!(
+            check_quota(full, quota, UsageDelta { notes: -1, bytes: -5 }),
+            Ok(Usage { notes: 1, bytes: 7 })
+        );
+        assert_eq!(
+            check_quota(Usage::default(), quota, UsageDelta::create(10)),
+            Ok(Usage { notes: 1, bytes: 10 })
+        );
+    }
+}
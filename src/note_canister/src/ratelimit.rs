@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::config::{RateLimit, RateLimits};
+
+// --- Constants ---
+// Tokens are tracked in thousandths so slow refill rates still accrue between calls.
+const MILLI: u64 = 1_000;
+const NANOS_PER_MINUTE: u128 = 60_000_000_000;
+
+// --- Data Structures ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, CandidType, Deserialize)]
+pub enum RateLimitedMethod {
+    Create,
+    Update,
+    Delete,
+}
+
+impl RateLimitedMethod {
+    fn name(self) -> &'static str {
+        match self {
+            RateLimitedMethod::Create => "create",
+            RateLimitedMethod::Update => "update",
+            RateLimitedMethod::Delete => "delete",
+        }
+    }
+
+    fn limit(self, limits: &RateLimits) -> RateLimit {
+        match self {
+            RateLimitedMethod::Create => limits.create,
+            RateLimitedMethod::Update => limits.update,
+            RateLimitedMethod::Delete => limits.delete,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum RateLimitError {
+    /// The call needs more tokens than the bucket holds when full, so it can
+    /// never succeed as made; split it into calls of at most `burst` operations.
+    OverBurst { method: RateLimitedMethod, burst: u32 },
+    /// The bucket is empty for now.
+    RetryAfter { method: RateLimitedMethod, seconds: u64 },
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RateLimitError::OverBurst { method, burst } => write!(
+                f,
+                "Rate limited: at most {} {} operations are allowed at once.",
+                burst,
+                method.name()
+            ),
+            RateLimitError::RetryAfter { method, seconds } => write!(
+                f,
+                "Rate limited: too many {} calls. Retry after {} seconds.",
+                method.name(),
+                seconds
+            ),
+        }
+    }
+}
+
+/// Calls that still report errors as text get the message.
+impl From<RateLimitError> for String {
+    fn from(error: RateLimitError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Error of the single-note write calls, telling rate limiting apart from
+/// every other failure.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub enum WriteError {
+    RateLimited(RateLimitError),
+    Rejected(String),
+}
+
+impl From<RateLimitError> for WriteError {
+    fn from(error: RateLimitError) -> Self {
+        WriteError::RateLimited(error)
+    }
+}
+
+impl From<String> for WriteError {
+    fn from(message: String) -> Self {
+        WriteError::Rejected(message)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    milli_tokens: u64,
+    refilled_at: u64,
+}
+
+/// Token buckets keyed by caller and method. A caller without a bucket has a
+/// full one, so buckets are created on first use and dropped once refilled.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: HashMap<(Principal, RateLimitedMethod), Bucket>,
+}
+
+impl RateLimiter {
+    /// Checks that `caller` could spend `cost` tokens on each method, without spending them.
+    pub fn check(
+        &self,
+        limits: &RateLimits,
+        caller: Principal,
+        now: u64,
+        costs: &[(RateLimitedMethod, u32)],
+    ) -> Result<(), RateLimitError> {
+        for &(method, cost) in costs {
+            let limit = method.limit(limits);
+            if cost > limit.burst {
+                return Err(RateLimitError::OverBurst { method, burst: limit.burst });
+            }
+            let available = self.available(limit, caller, method, now);
+            let needed = cost as u64 * MILLI;
+            if available < needed {
+                return Err(RateLimitError::RetryAfter {
+                    method,
+                    seconds: retry_after_seconds(limit, needed - available),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whole tokens `caller` could spend on `method` right now.
+    pub fn tokens(&self, limits: &RateLimits, caller: Principal, now: u64, method: RateLimitedMethod) -> u32 {
+        (self.available(method.limit(limits), caller, method, now) / MILLI) as u32
+    }
+
+    /// Spends the tokens if every method has enough; otherwise spends nothing.
+    pub fn try_acquire(
+        &mut self,
+        limits: &RateLimits,
+        caller: Principal,
+        now: u64,
+        costs: &[(RateLimitedMethod, u32)],
+    ) -> Result<(), RateLimitError> {
+        self.check(limits, caller, now, costs)?;
+        for &(method, cost) in costs {
+            let available = self.available(method.limit(limits), caller, method, now);
+            self.buckets.insert(
+                (caller, method),
+                Bucket {
+                    milli_tokens: available - cost as u64 * MILLI,
+                    refilled_at: now,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Drops buckets that have refilled completely, which behave like missing ones.
+    pub fn prune(&mut self, limits: &RateLimits, now: u64) {
+        self.buckets.retain(|&(_, method), bucket| {
+            let limit = method.limit(limits);
+            refill(limit, *bucket, now) < limit.burst as u64 * MILLI
+        });
+    }
+
+    fn available(&self, limit: RateLimit, caller: Principal, method: RateLimitedMethod, now: u64) -> u64 {
+        match self.buckets.get(&(caller, method)) {
+            Some(bucket) => refill(limit, *bucket, now),
+            None => limit.burst as u64 * MILLI,
+        }
+    }
+}
+
+fn refill(limit: RateLimit, bucket: Bucket, now: u64) -> u64 {
+    let elapsed = now.saturating_sub(bucket.refilled_at) as u128;
+    let earned = elapsed * limit.refill_per_minute as u128 * MILLI as u128 / NANOS_PER_MINUTE;
+    let capacity = limit.burst as u64 * MILLI;
+    (bucket.milli_tokens as u128 + earned).min(capacity as u128) as u64
+}
+
+fn retry_after_seconds(limit: RateLimit, missing_milli_tokens: u64) -> u64 {
+    let per_minute = limit.refill_per_minute.max(1) as u64 * MILLI;
+    (missing_milli_tokens * 60).div_ceil(per_minute).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn limits() -> RateLimits {
+        let limit = RateLimit {
+            burst: 2,
+            refill_per_minute: 6,
+        };
+        RateLimits {
+            create: limit,
+            update: limit,
+            delete: limit,
+        }
+    }
+
+    #[test]
+    fn test_bucket_drains_and_refills() {
+        let mut limiter = RateLimiter::default();
+        let caller = Principal::from_slice(&[1; 29]);
+        let create = [(RateLimitedMethod::Create, 1)];
+
+        assert!(limiter.try_acquire(&limits(), caller, 0, &create).is_ok());
+        assert!(limiter.try_acquire(&limits(), caller, 0, &create).is_ok());
+        assert_eq!(
+            limiter.try_acquire(&limits(), caller, 0, &create).unwrap_err(),
+            RateLimitError::RetryAfter { method: RateLimitedMethod::Create, seconds: 10 }
+        );
+        assert_eq!(limiter.tokens(&limits(), caller, 5 * SECOND, RateLimitedMethod::Create), 0);
+        // Other callers and methods have their own buckets.
+        assert!(limiter.check(&limits(), Principal::anonymous(), 0, &create).is_ok());
+        assert!(limiter.check(&limits(), caller, 0, &[(RateLimitedMethod::Delete, 2)]).is_ok());
+
+        assert!(limiter.check(&limits(), caller, 9 * SECOND, &create).is_err());
+        assert_eq!(limiter.tokens(&limits(), caller, 10 * SECOND, RateLimitedMethod::Create), 1);
+        assert!(limiter.try_acquire(&limits(), caller, 10 * SECOND, &create).is_ok());
+
+        limiter.prune(&limits(), 10 * SECOND);
+        assert_eq!(limiter.buckets.len(), 1);
+        limiter.prune(&limits(), 30 * SECOND);
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn test_acquire_is_all_or_nothing() {
+        let mut limiter = RateLimiter::default();
+        let caller = Principal::from_slice(&[1; 29]);
+
+        let batch = [(RateLimitedMethod::Create, 1), (RateLimitedMethod::Update, 3)];
+        assert_eq!(
+            limiter.try_acquire(&limits(), caller, 0, &batch).unwrap_err().to_string(),
+            "Rate limited: at most 2 update operations are allowed at once."
+        );
+        assert!(limiter.buckets.is_empty());
+    }
+}
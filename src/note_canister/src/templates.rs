@@ -0,0 +1,210 @@
+// --- Note Templates ---
+// Templates are kept apart from notes and only become a note through
+// `create_note_from_template`. Their title and content may contain
+// `{{placeholder}}` variables that are filled in at that point.
+
+use std::collections::{BTreeMap, HashMap};
+
+use candid::{CandidType, Deserialize, Principal};
+
+use crate::datetime::format_rfc3339;
+
+// --- Constants ---
+pub const MAX_TEMPLATES_PER_OWNER: usize = 50;
+const MAX_TEMPLATE_NAME_CHARS: usize = 100;
+const MAX_VARIABLES: usize = 20;
+const MAX_VARIABLE_VALUE_BYTES: usize = 512;
+/// Filled in by the canister; callers cannot override them.
+const BUILT_IN_PLACEHOLDERS: [&str; 4] = ["date", "time", "datetime", "author"];
+
+// --- Data Structures ---
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Template {
+    pub id: u64,
+    pub owner: Principal,
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    pub created_at: u64,
+    pub updated_at: Option<u64>,
+}
+
+/// Fields set by `create_template` and `update_template`.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct TemplateInput {
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+}
+
+/// Value for a caller-defined `{{name}}` placeholder.
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Deserialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Deserialize, serde::Serialize)]
+pub struct Templates {
+    last_id: u64,
+    templates: BTreeMap<u64, Template>,
+}
+
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Template name cannot be empty.".to_string());
+    }
+    if name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+        return Err(format!("Template name exceeds {} characters.", MAX_TEMPLATE_NAME_CHARS));
+    }
+    Ok(name.to_string())
+}
+
+/// Values for every placeholder: the built-ins for `author` at `now`, then the
+/// caller's variables.
+pub fn placeholder_values(
+    author: Principal,
+    now: u64,
+    variables: Vec<TemplateVariable>,
+) -> Result<HashMap<String, String>, String> {
+    if variables.len() > MAX_VARIABLES {
+        return Err(format!("At most {} template variables are allowed.", MAX_VARIABLES));
+    }
+    let datetime = format_rfc3339(now);
+    let mut values = HashMap::from([
+        ("date".to_string(), datetime[..10].to_string()),
+        ("time".to_string(), datetime[11..16].to_string()),
+        ("datetime".to_string(), datetime.clone()),
+        ("author".to_string(), author.to_text()),
+    ]);
+    for variable in variables {
+        let valid_name = !variable.name.is_empty()
+            && variable.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "Invalid template variable name '{}': use lowercase letters, digits and underscores.",
+                variable.name
+            ));
+        }
+        if BUILT_IN_PLACEHOLDERS.contains(&variable.name.as_str()) {
+            return Err(format!("Template variable '{}' is filled in automatically.", variable.name));
+        }
+        if variable.value.len() > MAX_VARIABLE_VALUE_BYTES {
+            return Err(format!(
+                "Template variable '{}' exceeds {} byte limit.",
+                variable.name, MAX_VARIABLE_VALUE_BYTES
+            ));
+        }
+        values.insert(variable.name, variable.value);
+    }
+    Ok(values)
+}
+
+/// Replaces each `{{name}}` that has a value. Other braces are left as written.
+pub fn render(text: &str, values: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}").and_then(|end| values.get(after[..end].trim()).map(|value| (end, value))) {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+impl Templates {
+    /// `owner`'s template `id`.
+    pub fn owned(&self, owner: Principal, id: u64) -> Result<&Template, String> {
+        self.templates
+            .get(&id)
+            .filter(|template| template.owner == owner)
+            .ok_or_else(|| "Template not found.".to_string())
+    }
+
+    pub fn owned_by(&self, owner: Principal) -> Vec<Template> {
+        self.templates.values().filter(|template| template.owner == owner).cloned().collect()
+    }
+
+    /// Stores an already validated template and returns its id.
+    pub fn insert(&mut self, owner: Principal, now: u64, input: TemplateInput) -> Result<u64, String> {
+        if self.templates.values().filter(|template| template.owner == owner).count() >= MAX_TEMPLATES_PER_OWNER {
+            return Err(format!("You can keep at most {} templates.", MAX_TEMPLATES_PER_OWNER));
+        }
+        self.last_id += 1;
+        self.templates.insert(
+            self.last_id,
+            Template {
+                id: self.last_id,
+                owner,
+                name: input.name,
+                title: input.title,
+                content: input.content,
+                tags: input.tags,
+                created_at: now,
+                updated_at: None,
+            },
+        );
+        Ok(self.last_id)
+    }
+
+    pub fn replace(&mut self, owner: Principal, id: u64, now: u64, input: TemplateInput) -> Result<(), String> {
+        self.owned(owner, id)?;
+        let template = self.templates.get_mut(&id).expect("checked above");
+        template.name = input.name;
+        template.title = input.title;
+        template.content = input.content;
+        template.tags = input.tags;
+        template.updated_at = Some(now);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, owner: Principal, id: u64) -> Result<(), String> {
+        self.owned(owner, id)?;
+        self.templates.remove(&id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_fills_known_placeholders() {
+        let author = Principal::from_slice(&[1; 29]);
+        let variables = vec![TemplateVariable {
+            name: "team".to_string(),
+            value: "Core".to_string(),
+        }];
+        // 2024-03-05T09:07:00Z
+        let values = placeholder_values(author, 1_709_629_620_000_000_000, variables).unwrap();
+        assert_eq!(
+            render("{{team}} sync {{ date }} {{time}} by {{author}}, {{unknown}} {{open", &values),
+            format!("Core sync 2024-03-05 09:07 by {}, {{{{unknown}}}} {{{{open", author)
+        );
+
+        let reserved = vec![TemplateVariable {
+            name: "date".to_string(),
+            value: "x".to_string(),
+        }];
+        assert!(placeholder_values(author, 0, reserved).is_err());
+        let invalid = vec![TemplateVariable {
+            name: "Team Name".to_string(),
+            value: "x".to_string(),
+        }];
+        assert!(placeholder_values(author, 0, invalid).is_err());
+    }
+}
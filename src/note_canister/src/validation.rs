@@ -0,0 +1,208 @@
+// --- Title and Content Validation ---
+// Every plaintext write (create, update, batch and import) runs through
+// `normalize_note`, which cleans the text according to the configured policy
+// and then rejects whatever is still invalid.
+
+use std::fmt;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::config::ValidationPolicy;
+
+// --- Data Structures ---
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Content,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::Title => "Title",
+            Field::Content => "Content",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    EmptyTitle,
+    EmptyContent,
+    TooLarge { max_bytes: usize, actual: usize },
+    TitleTooLong { max_chars: u32, actual: usize },
+    TooManyLines { max_lines: u32, actual: usize },
+    /// A control character, or a line break in the title.
+    ForbiddenCharacter { field: Field, character: char },
+    /// A bidirectional embedding, override or isolate that is not closed on
+    /// the same line, or a closer without an opener.
+    UnbalancedBidi { field: Field },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::EmptyTitle => write!(f, "Title cannot be empty."),
+            ValidationError::EmptyContent => write!(f, "Content cannot be empty."),
+            ValidationError::TooLarge { max_bytes, actual } => write!(
+                f,
+                "Note (title + content) exceeds {} byte limit. Current size: {}",
+                max_bytes, actual
+            ),
+            ValidationError::TitleTooLong { max_chars, actual } => {
+                write!(f, "Title exceeds {} characters. Current length: {}", max_chars, actual)
+            }
+            ValidationError::TooManyLines { max_lines, actual } => {
+                write!(f, "Content exceeds {} lines. Current line count: {}", max_lines, actual)
+            }
+            ValidationError::ForbiddenCharacter { field, character } => {
+                write!(f, "{} contains a forbidden character U+{:04X}.", field, *character as u32)
+            }
+            ValidationError::UnbalancedBidi { field } => {
+                write!(f, "{} contains unbalanced bidirectional formatting characters.", field)
+            }
+        }
+    }
+}
+
+impl From<ValidationError> for String {
+    fn from(error: ValidationError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Applies the policy's cleanups to `title` and `content`, then checks them.
+/// Returns the text to store.
+pub fn normalize_note(
+    policy: &ValidationPolicy,
+    max_bytes: usize,
+    title: &str,
+    content: &str,
+) -> Result<(String, String), ValidationError> {
+    let mut title = title.to_string();
+    // Line endings are always stored as `\n`.
+    let mut content = content.replace("\r\n", "\n").replace('\r', "\n");
+    if policy.normalize_nfc {
+        title = title.nfc().collect();
+        content = content.nfc().collect();
+    }
+    if policy.trim_whitespace {
+        title = title.trim().to_string();
+        content = content.trim().to_string();
+    }
+
+    if title.is_empty() {
+        return Err(ValidationError::EmptyTitle);
+    }
+    if content.is_empty() {
+        return Err(ValidationError::EmptyContent);
+    }
+    if title.len() + content.len() > max_bytes {
+        return Err(ValidationError::TooLarge {
+            max_bytes,
+            actual: title.len() + content.len(),
+        });
+    }
+    let title_chars = title.chars().count();
+    if title_chars > policy.max_title_chars as usize {
+        return Err(ValidationError::TitleTooLong {
+            max_chars: policy.max_title_chars,
+            actual: title_chars,
+        });
+    }
+    let lines = content.lines().count();
+    if lines > policy.max_lines as usize {
+        return Err(ValidationError::TooManyLines {
+            max_lines: policy.max_lines,
+            actual: lines,
+        });
+    }
+
+    if let Some(character) = title.chars().find(|c| *c == '\n' || (policy.forbid_control_chars && c.is_control())) {
+        return Err(ValidationError::ForbiddenCharacter {
+            field: Field::Title,
+            character,
+        });
+    }
+    if policy.forbid_control_chars {
+        if let Some(character) = content.chars().find(|c| c.is_control() && *c != '\n' && *c != '\t') {
+            return Err(ValidationError::ForbiddenCharacter {
+                field: Field::Content,
+                character,
+            });
+        }
+    }
+    if !bidi_balanced(&title) {
+        return Err(ValidationError::UnbalancedBidi { field: Field::Title });
+    }
+    if !content.lines().all(bidi_balanced) {
+        return Err(ValidationError::UnbalancedBidi { field: Field::Content });
+    }
+
+    Ok((title, content))
+}
+
+/// Embeddings and overrides (LRE, RLE, LRO, RLO) close with PDF; isolates
+/// (LRI, RLI, FSI) close with PDI. Unclosed ones can reorder the text that
+/// follows them, which is how "Trojan Source" style spoofing works.
+fn bidi_balanced(line: &str) -> bool {
+    let mut open = Vec::new();
+    for c in line.chars() {
+        match c {
+            '\u{202A}'..='\u{202B}' | '\u{202D}'..='\u{202E}' => open.push('\u{202C}'),
+            '\u{2066}'..='\u{2068}' => open.push('\u{2069}'),
+            '\u{202C}' | '\u{2069}' if open.pop() != Some(c) => return false,
+            _ => {}
+        }
+    }
+    open.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ValidationPolicy {
+        ValidationPolicy {
+            max_title_chars: 10,
+            max_lines: 3,
+            normalize_nfc: true,
+            trim_whitespace: true,
+            forbid_control_chars: true,
+        }
+    }
+
+    #[test]
+    fn test_normalize_cleans_text() {
+        let (title, content) = normalize_note(&policy(), 1024, "  Cafe\u{301} ", "a\r\nb\t\n").unwrap();
+        assert_eq!(title, "Caf\u{e9}");
+        assert_eq!(content, "a\nb");
+
+        let raw = ValidationPolicy {
+            normalize_nfc: false,
+            trim_whitespace: false,
+            ..policy()
+        };
+        assert_eq!(normalize_note(&raw, 1024, " T", "x ").unwrap(), (" T".to_string(), "x ".to_string()));
+    }
+
+    #[test]
+    fn test_normalize_rejects_invalid_text() {
+        let check = |title: &str, content: &str| normalize_note(&policy(), 64, title, content).unwrap_err();
+
+        assert_eq!(check(" \t ", "Body"), ValidationError::EmptyTitle);
+        assert_eq!(check("T", "x".repeat(64).as_str()), ValidationError::TooLarge { max_bytes: 64, actual: 65 });
+        assert_eq!(check("Much too long", "Body"), ValidationError::TitleTooLong { max_chars: 10, actual: 13 });
+        assert_eq!(check("T", "1\n2\n3\n4"), ValidationError::TooManyLines { max_lines: 3, actual: 4 });
+        assert_eq!(
+            check("T", "bell\u{7}"),
+            ValidationError::ForbiddenCharacter { field: Field::Content, character: '\u{7}' }
+        );
+        assert_eq!(check("T", "a\u{202E}b"), ValidationError::UnbalancedBidi { field: Field::Content });
+        assert!(normalize_note(&policy(), 64, "T", "a\u{202E}b\u{202C} \u{2067}c\u{2069}").is_ok());
+        assert_eq!(
+            check("T", "a\u{202E}b\u{2069}"),
+            ValidationError::UnbalancedBidi { field: Field::Content }
+        );
+    }
+}
@@ -0,0 +1,195 @@
+// --- Minimal ZIP Reader ---
+// Reads the central directory of a ZIP archive and inflates its entries.
+// Only what export archives use is supported: stored and deflated entries,
+// no encryption, no multi-disk archives and no ZIP64.
+
+const EOCD_SIGNATURE: u32 = 0x0605_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const EOCD_MIN_LEN: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Returns every file entry in `bytes`, skipping directories. The size of each
+/// extracted entry is taken from `budget`, which callers share across nested
+/// archives; fails once it would run out.
+pub fn read_archive(bytes: &[u8], budget: &mut usize) -> Result<Vec<ZipEntry>, String> {
+    let eocd = find_eocd(bytes).ok_or("Not a ZIP archive: end of central directory not found.")?;
+    let entry_count = read_u16(bytes, eocd + 10)? as usize;
+    let directory_offset = read_u32(bytes, eocd + 16)? as usize;
+
+    let mut entries = Vec::with_capacity(entry_count);
+    let mut pos = directory_offset;
+
+    for _ in 0..entry_count {
+        if read_u32(bytes, pos)? != CENTRAL_HEADER_SIGNATURE {
+            return Err("Corrupt ZIP archive: bad central directory header.".to_string());
+        }
+        let flags = read_u16(bytes, pos + 8)?;
+        let method = read_u16(bytes, pos + 10)?;
+        let compressed_size = read_u32(bytes, pos + 20)? as usize;
+        let uncompressed_size = read_u32(bytes, pos + 24)? as usize;
+        let name_len = read_u16(bytes, pos + 28)? as usize;
+        let extra_len = read_u16(bytes, pos + 30)? as usize;
+        let comment_len = read_u16(bytes, pos + 32)? as usize;
+        let local_offset = read_u32(bytes, pos + 42)? as usize;
+        let name_bytes = slice(bytes, pos + 46, name_len)?;
+        pos += 46 + name_len + extra_len + comment_len;
+
+        let name = String::from_utf8_lossy(name_bytes).replace('\\', "/");
+        if name.ends_with('/') {
+            continue;
+        }
+        if flags & 0x1 != 0 {
+            return Err(format!("Encrypted ZIP entries are not supported: {}", name));
+        }
+
+        // Checked before extracting so nothing larger than the budget is allocated.
+        if uncompressed_size > *budget {
+            return Err("ZIP archive expands beyond the size limit.".to_string());
+        }
+
+        if read_u32(bytes, local_offset)? != LOCAL_HEADER_SIGNATURE {
+            return Err(format!("Corrupt ZIP archive: bad local header for {}", name));
+        }
+        let local_name_len = read_u16(bytes, local_offset + 26)? as usize;
+        let local_extra_len = read_u16(bytes, local_offset + 28)? as usize;
+        let data_start = local_offset + 30 + local_name_len + local_extra_len;
+        let compressed = slice(bytes, data_start, compressed_size)?;
+
+        let data = match method {
+            METHOD_STORED if compressed_size != uncompressed_size => {
+                return Err(format!("Corrupt ZIP archive: size mismatch for stored entry {}", name));
+            }
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, uncompressed_size)
+                    .map_err(|e| format!("Failed to inflate {}: {:?}", name, e.status))?
+            }
+            other => return Err(format!("Unsupported compression method {} for {}", other, name)),
+        };
+        // Charge what was actually extracted: several directory records may
+        // point at the same local header.
+        *budget -= data.len();
+        entries.push(ZipEntry { name, data });
+    }
+
+    Ok(entries)
+}
+
+fn find_eocd(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < EOCD_MIN_LEN {
+        return None;
+    }
+    // The record sits at the end, followed by a comment of at most 64 KiB.
+    let earliest = bytes.len().saturating_sub(EOCD_MIN_LEN + u16::MAX as usize);
+    (earliest..=bytes.len() - EOCD_MIN_LEN)
+        .rev()
+        .find(|&pos| read_u32(bytes, pos).ok() == Some(EOCD_SIGNATURE))
+}
+
+fn slice(bytes: &[u8], start: usize, len: usize) -> Result<&[u8], String> {
+    start
+        .checked_add(len)
+        .and_then(|end| bytes.get(start..end))
+        .ok_or_else(|| "Corrupt ZIP archive: truncated data.".to_string())
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16, String> {
+    let b = slice(bytes, pos, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32, String> {
+    let b = slice(bytes, pos, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds an archive of stored (uncompressed) entries.
+    pub(crate) fn build_stored_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for (name, data) in files {
+            let offset = out.len() as u32;
+            let mut header = Vec::new();
+            header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            header.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(data);
+
+            directory.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            directory.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        let directory_offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&EOCD_SIGNATURE.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&directory_offset.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn test_read_stored_archive() {
+        let archive = build_stored_archive(&[("a.md", b"# A"), ("dir/b.csv", b"x,y")]);
+        let mut budget = 1024;
+        let entries = read_archive(&archive, &mut budget).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, "dir/b.csv");
+        assert_eq!(entries[1].data, b"x,y");
+        assert_eq!(budget, 1024 - 6);
+
+        assert!(read_archive(&archive, &mut 4).is_err());
+        assert!(read_archive(b"not a zip at all, definitely not", &mut 1024).is_err());
+    }
+
+    #[test]
+    fn test_stored_entry_sizes_must_match() {
+        let mut archive = build_stored_archive(&[("a.md", b"# A")]);
+        // Declare a smaller uncompressed size than the stored data.
+        let central = 30 + "a.md".len() + 3;
+        archive[central + 24..central + 28].copy_from_slice(&1u32.to_le_bytes());
+        assert!(read_archive(&archive, &mut 1024).unwrap_err().contains("size mismatch"));
+    }
+
+    #[test]
+    fn test_read_deflated_entry() {
+        let text = b"hello hello hello hello hello";
+        let compressed = miniz_oxide::deflate::compress_to_vec(text, 6);
+        let mut archive = build_stored_archive(&[("c.md", &compressed)]);
+        // Patch method and uncompressed size in both headers.
+        archive[8] = METHOD_DEFLATED as u8;
+        archive[22..26].copy_from_slice(&(text.len() as u32).to_le_bytes());
+        let central = 30 + "c.md".len() + compressed.len();
+        archive[central + 10] = METHOD_DEFLATED as u8;
+        archive[central + 24..central + 28].copy_from_slice(&(text.len() as u32).to_le_bytes());
+
+        let entries = read_archive(&archive, &mut 1024).unwrap();
+        assert_eq!(entries[0].data, text);
+    }
+}